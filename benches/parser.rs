@@ -1,10 +1,11 @@
 use std::fs::File;
-use std::io::{BufRead, BufReader};
+use std::io::BufReader;
 use std::path::PathBuf;
 
 use divan::Bencher;
 use pinix::action::Action;
 use pinix::action_raw::RawAction;
+use pinix::wrapper::record::{RecordEntry, RecordReader};
 
 fn main() {
     divan::main();
@@ -13,13 +14,16 @@ fn main() {
 fn load_example(example: &str) -> Vec<String> {
     let path: PathBuf = ["examples", example].iter().collect();
     let file = File::open(path).expect("could not open example");
-    let reader = BufReader::new(file);
+    let reader = RecordReader::new(BufReader::new(file));
 
     reader
-        .lines()
-        .filter_map(|line| {
-            let line = line.expect("could not read line");
-            let msg = line.splitn(3, ' ').nth(2)?.strip_prefix("@nix ")?;
+        .filter_map(|entry| {
+            let RecordEntry::Line(line) = entry.expect("could not read line") else {
+                return None;
+            };
+
+            let line = String::from_utf8(line.payload).ok()?;
+            let msg = line.trim_end_matches('\n').strip_prefix("@nix ")?;
             Some(msg.to_string())
         })
         .collect()
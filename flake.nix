@@ -49,9 +49,12 @@
                 let
                   wrappers = [
                     "nix"
+                    "nix-build"
                     "nix-collect-garbage"
+                    "nix-env"
                     "nixos-rebuild"
                     "nix-shell"
+                    "nix-store"
                   ];
                   install-wrappers = lib.lists.forEach wrappers (
                     nix-cmd:
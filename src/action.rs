@@ -12,10 +12,11 @@ use crate::action_raw::RawAction;
 // --- ActionType
 // ---
 
-#[derive(Clone, Copy, Debug, Deserialize_repr)]
+#[derive(Clone, Copy, Debug, Default, Deserialize_repr)]
 #[repr(u8)]
 #[derive(Eq, PartialEq)]
 pub enum ActionType {
+    #[default]
     Unknown = 0,
     CopyPath = 100,
     FileTransfer = 101,
@@ -31,12 +32,6 @@ pub enum ActionType {
     BuildWaiting = 111,
 }
 
-impl Default for ActionType {
-    fn default() -> Self {
-        Self::Unknown
-    }
-}
-
 // ---
 // --- BuildStepId
 // ---
@@ -90,9 +85,10 @@ pub enum StartFields<'a> {
     Builds,
     Build {
         target: Cow<'a, str>,
-        source: Cow<'a, str>, // TODO: check
-        val1: u64,
-        val2: u64,
+        /// URI of the remote builder, empty for local builds
+        machine: Cow<'a, str>,
+        round: u64,
+        nb_rounds: u64,
     },
     OptimiseStore,
     VerifyPaths,
@@ -101,13 +97,57 @@ pub enum StartFields<'a> {
         target: Cow<'a, str>,
     },
     QueryPathInfo,
-    PostBuildHook,
-    BuildWaiting,
+    PostBuildHook {
+        target: Cow<'a, str>,
+    },
+    BuildWaiting {
+        /// Builder the derivation is waiting for, if Nix reports it
+        machine: Option<Cow<'a, str>>,
+    },
+}
+
+/// Direction of a `CopyPath`, seen from the local store
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Hash)]
+pub enum CopyDirection {
+    Download,
+    Upload,
+}
+
+impl CopyDirection {
+    pub fn new(origin: &str, destination: &str) -> Self {
+        if is_local_store(origin) && !is_local_store(destination) {
+            Self::Upload
+        } else {
+            Self::Download
+        }
+    }
+
+    /// The remote store involved in the copy, given its origin and destination
+    pub fn remote<'s>(&self, origin: &'s str, destination: &'s str) -> &'s str {
+        match self {
+            Self::Download => origin,
+            Self::Upload => destination,
+        }
+    }
+}
+
+/// Check if a store URI refers to the store of the machine Nix runs on
+fn is_local_store(uri: &str) -> bool {
+    let uri = uri.split('?').next().unwrap_or(uri);
+    matches!(uri, "" | "local" | "daemon" | "auto")
+        || uri.starts_with('/')
+        || uri.starts_with("unix://")
 }
 
 #[derive(Debug, Eq, PartialEq)]
 pub enum ResultFields<'a> {
+    FileLinked {
+        size: u64,
+        blocks: u64,
+    },
     BuildLogLine(Cow<'a, str>),
+    UntrustedPath(Cow<'a, str>),
+    CorruptedPath(Cow<'a, str>),
     SetPhase(&'a str),
     Progress {
         done: u64,
@@ -119,6 +159,7 @@ pub enum ResultFields<'a> {
         action: ActionType,
         expected: u64,
     },
+    PostBuildLogLine(Cow<'a, str>),
 }
 
 #[derive(Debug, Eq, PartialEq)]
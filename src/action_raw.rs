@@ -90,14 +90,14 @@ impl<'a> TryFrom<RawAction<'a>> for Action<'a> {
                     ActionType::Build => {
                         let raw_fields = val.fields.ok_or_else(|| missing("fields"))?.get();
 
-                        let (target, source, val1, val2) =
+                        let (target, machine, round, nb_rounds) =
                             serde_json::from_str(raw_fields).context("invalid fields")?;
 
                         StartFields::Build {
                             target,
-                            source,
-                            val1,
-                            val2,
+                            machine,
+                            round,
+                            nb_rounds,
                         }
                     }
                     ActionType::OptimiseStore => StartFields::OptimiseStore,
@@ -105,14 +105,37 @@ impl<'a> TryFrom<RawAction<'a>> for Action<'a> {
                     ActionType::Substitute => {
                         let raw_fields = val.fields.ok_or_else(|| missing("fields"))?.get();
 
-                        let (source, target) =
+                        // Nix lists the store path before the substituter
+                        let (target, source) =
                             serde_json::from_str(raw_fields).context("invalid fields")?;
 
                         StartFields::Substitute { source, target }
                     }
                     ActionType::QueryPathInfo => StartFields::QueryPathInfo,
-                    ActionType::PostBuildHook => StartFields::PostBuildHook,
-                    ActionType::BuildWaiting => StartFields::BuildWaiting,
+                    ActionType::PostBuildHook => {
+                        let raw_fields = val.fields.ok_or_else(|| missing("fields"))?.get();
+
+                        let [target] =
+                            serde_json::from_str(raw_fields).context("invalid fields")?;
+
+                        StartFields::PostBuildHook { target }
+                    }
+                    ActionType::BuildWaiting => {
+                        let machine = match val.fields {
+                            Some(raw_fields) => {
+                                let fields: Vec<Cow<str>> = serde_json::from_str(raw_fields.get())
+                                    .context("invalid fields")?;
+
+                                fields
+                                    .into_iter()
+                                    .next()
+                                    .filter(|machine| !machine.is_empty())
+                            }
+                            None => None,
+                        };
+
+                        StartFields::BuildWaiting { machine }
+                    }
                 };
 
                 Action::Start {
@@ -128,13 +151,24 @@ impl<'a> TryFrom<RawAction<'a>> for Action<'a> {
                 let raw_fields = val.fields.ok_or_else(|| missing("fields"))?.get();
 
                 let fields = match val.any_type.ok_or_else(|| missing("type"))? {
-                    100 => todo!("FileLinked({raw_fields})"),
+                    100 => {
+                        let (size, blocks) =
+                            serde_json::from_str(raw_fields).context("invalid fields")?;
+
+                        ResultFields::FileLinked { size, blocks }
+                    }
                     101 => {
                         let [line] = serde_json::from_str(raw_fields).context("invalid fields")?;
                         ResultFields::BuildLogLine(line)
                     }
-                    102 => todo!("UntrustedPath({raw_fields})"),
-                    103 => todo!("CorruptedPath({raw_fields})"),
+                    102 => {
+                        let [path] = serde_json::from_str(raw_fields).context("invalid fields")?;
+                        ResultFields::UntrustedPath(path)
+                    }
+                    103 => {
+                        let [path] = serde_json::from_str(raw_fields).context("invalid fields")?;
+                        ResultFields::CorruptedPath(path)
+                    }
                     104 => {
                         let [phase] = serde_json::from_str(raw_fields).context("invalid fields")?;
                         ResultFields::SetPhase(phase)
@@ -151,10 +185,15 @@ impl<'a> TryFrom<RawAction<'a>> for Action<'a> {
                         }
                     }
                     106 => {
-                        let (action, expected) = serde_json::from_str(raw_fields).unwrap();
+                        let (action, expected) =
+                            serde_json::from_str(raw_fields).context("invalid fields")?;
+
                         ResultFields::SetExpected { action, expected }
                     }
-                    107 => todo!("PostBuildLogLine({raw_fields})"),
+                    107 => {
+                        let [line] = serde_json::from_str(raw_fields).context("invalid fields")?;
+                        ResultFields::PostBuildLogLine(line)
+                    }
                     v => anyhow::bail!("Unknown result type `{v}`"),
                 };
 
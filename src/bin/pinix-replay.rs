@@ -1,12 +1,13 @@
 use std::fs::File;
 
-use std::io::{BufRead, BufReader, Write};
+use std::io::{BufReader, Write};
 use std::path::PathBuf;
 use std::thread::sleep;
 use std::time::{Duration, Instant};
 
 use anyhow::Context;
 use clap::Parser;
+use pinix::wrapper::record::{RecordEntry, RecordReader};
 use pinix::wrapper::stream::OutputStream;
 
 #[derive(Debug, clap::Parser)]
@@ -40,36 +41,37 @@ fn main() -> anyhow::Result<()> {
     let mut stdout = std::io::stdout();
     let mut stderr = std::io::stderr();
 
-    for line in BufReader::new(file).lines() {
-        let line = line.context("could not read line from file")?;
-        let mut cols = line.splitn(3, ' ');
+    for entry in RecordReader::new(BufReader::new(file)) {
+        let line = match entry? {
+            RecordEntry::Header(_) => continue,
+            RecordEntry::Line(line) => line,
+            RecordEntry::Footer(footer) => {
+                // Mimic the recorded command up to its exit status
+                if footer.exit_code != 0 {
+                    std::process::exit(footer.exit_code);
+                }
 
-        let output: OutputStream = cols.next().context("missing output column")?.parse()?;
-
-        let delay: u128 = cols
-            .next()
-            .context("missing delay column")?
-            .parse()
-            .context("invalid delay")?;
+                break;
+            }
+        };
 
-        let delay = (1000. * (delay as f64 - 1000. * args.skip).clamp(0., f64::INFINITY)
+        let delay = (1000. * (line.time_ms as f64 - 1000. * args.skip).clamp(0., f64::INFINITY)
             / args.factor) as u128;
 
-        let line = cols.next().unwrap_or("");
         let to_wait = delay.saturating_sub(start_time.elapsed().as_micros());
         sleep(Duration::from_micros(to_wait as _));
 
-        match output {
+        match line.output {
             OutputStream::StdOut => {
                 stdout
-                    .write_all(format!("{line}\n").as_bytes())
+                    .write_all(&line.payload)
                     .context("couldn't write to stdout")?;
 
                 stdout.flush().context("couldn't flush stdout")?;
             }
             OutputStream::StdErr => {
                 stderr
-                    .write_all(format!("{line}\n").as_bytes())
+                    .write_all(&line.payload)
                     .context("couldn't write to stderr")?;
 
                 stderr.flush().context("couldn't flush stderr")?;
@@ -1,8 +1,29 @@
-use pinix::wrapper::command::NixCommand;
+use std::process::ExitCode;
+
+use console::style;
+use pinix::wrapper::command::{NixCommand, EXIT_INTERNAL_ERROR};
 
 #[tokio::main(flavor = "current_thread")]
-async fn main() -> anyhow::Result<()> {
-    NixCommand::from_args(std::env::args().skip(1))
-        .exec_copycat()
-        .await
+async fn main() -> ExitCode {
+    let code = match run().await {
+        Ok(code) => code,
+        Err(err) => {
+            eprintln!("{}: {err:#}", style("error").bright().red().bold());
+            EXIT_INTERNAL_ERROR
+        }
+    };
+
+    ExitCode::from(u8::try_from(code).unwrap_or(u8::MAX))
+}
+
+async fn run() -> anyhow::Result<i32> {
+    let cmd = NixCommand::from_args(std::env::args().skip(1))?;
+
+    if let Some(path) = &cmd.args.replay {
+        cmd.exec_replay(path).await
+    } else if cmd.args.stdin {
+        cmd.exec_stdin().await
+    } else {
+        cmd.exec_copycat().await
+    }
 }
@@ -0,0 +1,103 @@
+use std::cell::RefCell;
+use std::rc::{Rc, Weak};
+
+use indicatif::{ProgressBar, WeakProgressBar};
+
+/// Progress bars displayed at some point of the run
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Frame {
+    /// Number of lines that were printed above the bars
+    pub printed: usize,
+    pub bars: Vec<String>,
+}
+
+/// A bar added to the state, along with bars which are kept right below it
+struct TrackedBar {
+    bar: WeakProgressBar,
+    below: Weak<RefCell<Vec<ProgressBar>>>,
+}
+
+/// Keep what would be drawn on the terminal instead of drawing it, which
+/// allows to check the output of handlers without a terminal.
+#[derive(Default)]
+pub struct Capture {
+    pub printed: Vec<String>,
+    pub frames: Vec<Frame>,
+    /// Bars in the order they are displayed below the separator
+    bars: Vec<TrackedBar>,
+}
+
+impl Capture {
+    /// Track a bar which was inserted right below the separator
+    pub fn track(&mut self, pb: &ProgressBar, below: Option<&Rc<RefCell<Vec<ProgressBar>>>>) {
+        self.bars.insert(
+            0,
+            TrackedBar {
+                bar: pb.downgrade(),
+                below: below.map(Rc::downgrade).unwrap_or_default(),
+            },
+        );
+    }
+
+    pub fn println(&mut self, msg: &str) {
+        self.printed.extend(
+            msg.lines()
+                .map(|line| console::strip_ansi_codes(line).into_owned()),
+        );
+    }
+
+    /// Save current state of the bars if it changed since last frame
+    pub fn snapshot(&mut self) {
+        self.bars.retain(|tracked| tracked.bar.upgrade().is_some());
+        let mut bars = Vec::new();
+
+        for tracked in &self.bars {
+            let below = tracked.below.upgrade();
+            let below = below.as_ref().map(|below| below.borrow());
+
+            if let Some(pb) = tracked.bar.upgrade().filter(|pb| !pb.is_finished()) {
+                bars.push(format_bar(&pb));
+            }
+
+            let lines = (below.iter().flat_map(|below| below.iter()))
+                .filter(|pb| !pb.is_finished())
+                .map(format_line);
+
+            bars.extend(lines);
+        }
+
+        if self.frames.last().map(|frame| &frame.bars) != Some(&bars) {
+            self.frames.push(Frame {
+                printed: self.printed.len(),
+                bars,
+            });
+        }
+    }
+}
+
+/// Text content of a line which is only made of a prefix and a message
+fn format_line(pb: &ProgressBar) -> String {
+    let line = format!("{} {}", pb.prefix(), pb.message());
+    console::strip_ansi_codes(&line).into_owned()
+}
+
+/// Text content of a bar, without its styling and timing information
+fn format_bar(pb: &ProgressBar) -> String {
+    let mut parts = Vec::new();
+    let prefix = pb.prefix();
+    let message = pb.message();
+
+    if !prefix.is_empty() {
+        parts.push(format!("[{prefix}]"));
+    }
+
+    if !message.is_empty() {
+        parts.push(message);
+    }
+
+    if let Some(len) = pb.length().filter(|len| *len > 0) {
+        parts.push(format!("{}/{len}", pb.position()));
+    }
+
+    console::strip_ansi_codes(&parts.join(" ")).into_owned()
+}
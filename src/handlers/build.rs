@@ -2,18 +2,21 @@ use std::time::Instant;
 
 use console::style;
 
-use crate::action::{Action, BuildStepId, StartFields};
+use crate::action::{Action, BuildStepId, ResultFields, StartFields};
 use crate::state::{Handler, HandlerResult, State};
-use crate::style::format_build_target;
+use crate::style::{format_build_target, match_machine_host};
 
 pub fn handle_new_build(state: &mut State, action: &Action) -> anyhow::Result<HandlerResult> {
     if let Action::Start {
-        start_type: StartFields::Build { target, .. },
+        start_type: StartFields::Build {
+            target, machine, ..
+        },
         id,
         ..
     } = action
     {
-        state.plug(Build::new(*id, target.to_string()));
+        let host = match_machine_host(machine).map(str::to_string);
+        state.plug(Build::new(*id, target.to_string(), host));
     }
 
     Ok(HandlerResult::Continue)
@@ -22,15 +25,54 @@ pub fn handle_new_build(state: &mut State, action: &Action) -> anyhow::Result<Ha
 struct Build {
     id: BuildStepId,
     target: String,
+    host: Option<String>,
     start: Instant,
+    phases: Vec<(String, Instant)>,
+    /// Description of the build once it stopped, until its outcome is known
+    stopped: Option<String>,
 }
 
 impl Build {
-    fn new(id: BuildStepId, target: String) -> Self {
+    fn new(id: BuildStepId, target: String, host: Option<String>) -> Self {
         Self {
             id,
             target,
+            host,
             start: Instant::now(),
+            phases: Vec::new(),
+            stopped: None,
+        }
+    }
+
+    /// Format time spent in each phase, assuming the build is now stopped
+    fn format_phases(&self) -> String {
+        let now = Instant::now();
+
+        let ends = (self.phases.iter().skip(1))
+            .map(|(_, start)| *start)
+            .chain(std::iter::once(now));
+
+        self.phases
+            .iter()
+            .zip(ends)
+            .map(|((phase, start), end)| format!("{phase} {:.0?}", end - *start))
+            .collect::<Vec<_>>()
+            .join(", ")
+    }
+
+    fn print_outcome(&mut self, state: &State) -> anyhow::Result<()> {
+        let Some(build) = self.stopped.take() else {
+            return Ok(());
+        };
+
+        if state.summary.is_failed_step(self.id) {
+            state.println(format!(
+                "{} {} {build}",
+                style("✗").red().bold(),
+                style("Failed to build").red().bold(),
+            ))
+        } else {
+            state.println(format!("{} Built {build}", style("✓").green()))
         }
     }
 }
@@ -38,21 +80,53 @@ impl Build {
 impl Handler for Build {
     fn on_action(&mut self, state: &mut State, action: &Action) -> anyhow::Result<HandlerResult> {
         match action {
+            Action::Result {
+                id,
+                fields: ResultFields::SetPhase(phase),
+            } if *id == self.id => {
+                self.phases.push((phase.to_string(), Instant::now()));
+            }
+
             Action::Stop { id } if *id == self.id => {
-                let icon = style("✓").green();
-                let detail = style(format!("({:.0?})", self.start.elapsed())).dim();
+                let target = match &self.host {
+                    Some(host) => format!(
+                        "{} on {}",
+                        format_build_target(&self.target),
+                        style(host).magenta()
+                    ),
+                    None => format_build_target(&self.target),
+                };
 
-                state.println(format!(
-                    "{icon} Built {} {detail}",
-                    format_build_target(&self.target)
-                ))?;
+                let detail = {
+                    if self.phases.is_empty() {
+                        style(format!("({:.0?})", self.start.elapsed())).dim()
+                    } else {
+                        style(format!(
+                            "({:.0?}: {})",
+                            self.start.elapsed(),
+                            self.format_phases()
+                        ))
+                        .dim()
+                    }
+                };
 
-                return Ok(HandlerResult::Close);
+                self.stopped = Some(format!("{target} {detail}"));
             }
 
             _ => {}
         }
 
+        // Nix may only report the failure of a build after it stopped, its
+        // outcome is printed once the error is received or the run is over
+        if self.stopped.is_some() && state.summary.is_failed_step(self.id) {
+            self.print_outcome(state)?;
+            return Ok(HandlerResult::Close);
+        }
+
         Ok(HandlerResult::Continue)
     }
+
+    fn on_finish(&mut self, state: &mut State) -> anyhow::Result<()> {
+        self.print_outcome(state)
+    }
 }
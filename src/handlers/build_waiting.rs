@@ -0,0 +1,76 @@
+use std::time::Duration;
+
+use console::style;
+use indicatif::{ProgressBar, ProgressFinish, ProgressStyle};
+
+use crate::action::{Action, BuildStepId, StartFields};
+use crate::state::{Handler, HandlerResult, State};
+use crate::style::{match_machine_host, template_style};
+use crate::util::indicatif_ext::ProgressBarExt;
+
+fn get_style(size: u16) -> ProgressStyle {
+    template_style(size, true, |_| "{wide_msg} {spinner}", |_| "").tick_chars("…  ")
+}
+
+pub fn handle_new_build_waiting(
+    state: &mut State,
+    action: &Action,
+) -> anyhow::Result<HandlerResult> {
+    if let Action::Start {
+        start_type: StartFields::BuildWaiting { machine },
+        id,
+        text,
+        ..
+    } = action
+    {
+        // Nix doesn't always tell which machine is busy, its own description
+        // of the activity is used instead
+        let message = match machine.as_deref().and_then(match_machine_host) {
+            Some(host) => format!("Waiting for a free build slot on {}", style(host).magenta()),
+            None if text.is_empty() => "Waiting for a free build slot".to_string(),
+            None => text.to_string(),
+        };
+
+        let handler = BuildWaiting::new(*id, message, state);
+        state.plug(handler);
+    }
+
+    Ok(HandlerResult::Continue)
+}
+
+/// A build waiting for a builder to be available
+struct BuildWaiting {
+    id: BuildStepId,
+    progress: ProgressBar,
+}
+
+impl BuildWaiting {
+    fn new(id: BuildStepId, message: String, state: &mut State) -> Self {
+        let progress = ProgressBar::new_spinner()
+            .with_style(get_style(state.term_size))
+            .with_message(message)
+            .with_finish(ProgressFinish::AndClear);
+
+        let progress = state.add(progress);
+        progress.spawn_steady_tick(Duration::from_secs(1));
+        Self { id, progress }
+    }
+}
+
+impl Handler for BuildWaiting {
+    fn on_action(&mut self, _state: &mut State, action: &Action) -> anyhow::Result<HandlerResult> {
+        match action {
+            Action::Stop { id } if *id == self.id => {
+                self.progress.finish_and_clear();
+                Ok(HandlerResult::Close)
+            }
+
+            _ => Ok(HandlerResult::Continue),
+        }
+    }
+
+    fn on_resize(&mut self, state: &mut State) -> anyhow::Result<()> {
+        self.progress.set_style(get_style(state.term_size));
+        Ok(())
+    }
+}
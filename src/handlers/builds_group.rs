@@ -1,4 +1,5 @@
 use std::rc::Rc;
+use std::time::{Duration, Instant};
 
 use console::style;
 use indexmap::IndexMap;
@@ -7,11 +8,22 @@ use once_cell::sync::Lazy;
 
 use crate::action::{Action, BuildStepId, ResultFields, StartFields};
 use crate::handlers::logs::{LogHandler, LogsWindow};
+use crate::input::Input;
 use crate::state::{Handler, HandlerResult, State};
-use crate::style::{format_short_build_target, template_style, MultiBar};
+use crate::style::{
+    format_eta, format_short_build_target, match_machine_host, template_style, MultiBar,
+};
 
 static C_RUN: Lazy<String> = Lazy::new(|| style("-").blue().bright().to_string());
 
+/// A build is slower than usual when it exceeds its estimate by this factor,
+/// and by at least `SLOW_MARGIN`
+const SLOW_FACTOR: f64 = 1.5;
+const SLOW_MARGIN: Duration = Duration::from_secs(10);
+
+/// Interval between two updates of estimated remaining times
+const ETA_REFRESH: Duration = Duration::from_secs(1);
+
 pub fn get_style(size: u16) -> ProgressStyle {
     template_style(
         size,
@@ -35,34 +47,147 @@ pub fn handle_new_builds_group(
     } = action
     {
         let progress = ProgressBar::new_spinner().with_style(get_style(state.term_size));
-        let progress = state.add(progress);
-        let logs_window = Rc::new(LogsWindow::new(state, &progress));
+        let logs_window = Rc::new(LogsWindow::new(state, progress));
+        let progress = logs_window.anchor().clone();
 
         state.plug(BuildGroup {
             id: *id,
             progress,
-            builds_formatted: IndexMap::new(),
+            running_builds: IndexMap::new(),
             logs_window,
             last_state: [0; 3],
+            nb_failed: 0,
+            last_refresh: Instant::now(),
         });
     }
 
     Ok(HandlerResult::Continue)
 }
 
+/// A build running in the group
+struct RunningBuild {
+    name: String,
+    host: Option<String>,
+    phase: Option<String>,
+    start: Instant,
+    /// Expected duration, if the derivation was built before
+    estimate: Option<Duration>,
+}
+
+impl RunningBuild {
+    /// Time left before the build is expected to finish
+    fn remaining(&self) -> Option<Duration> {
+        Some(self.estimate?.saturating_sub(self.start.elapsed()))
+    }
+
+    fn is_slow(&self) -> bool {
+        self.estimate.is_some_and(|estimate| {
+            self.start.elapsed() > estimate.mul_f64(SLOW_FACTOR).max(estimate + SLOW_MARGIN)
+        })
+    }
+
+    fn format(&self, selected: bool) -> String {
+        let mut result = {
+            if selected {
+                style(&self.name).bold().underlined().to_string()
+            } else {
+                self.name.clone()
+            }
+        };
+
+        if let Some(host) = &self.host {
+            result = format!("{result} on {}", style(host).magenta());
+        }
+
+        if let Some(phase) = &self.phase {
+            result = format!("{result}: {}", style(phase).dim());
+        }
+
+        if self.is_slow() {
+            result = format!("{result} {}", style("(slower than usual)").yellow());
+        } else if let Some(remaining) = self.remaining().filter(|rem| rem.as_secs() > 0) {
+            let remaining = format!("(~{} left)", format_eta(remaining));
+            result = format!("{result} {}", style(remaining).dim());
+        }
+
+        result
+    }
+}
+
 /// Keep track of current group of builds
 struct BuildGroup {
     id: BuildStepId,
     progress: ProgressBar,
-    builds_formatted: IndexMap<BuildStepId, String>,
+    running_builds: IndexMap<BuildStepId, RunningBuild>,
     logs_window: Rc<LogsWindow>,
     last_state: [u64; 3],
+    nb_failed: u64,
+    last_refresh: Instant,
 }
 
 impl BuildGroup {
-    fn update_message(&self) {
-        let all_builds: Vec<_> = self.builds_formatted.values().map(String::as_str).collect();
-        self.progress.set_message(all_builds.join(", "));
+    fn update_message(&mut self) {
+        let selected = self.logs_window.selected_id();
+
+        let all_builds: Vec<_> = (self.running_builds.iter())
+            .map(|(id, build)| build.format(selected == Some(*id)))
+            .collect();
+
+        let mut msg = all_builds.join(", ");
+
+        if let Some(eta) = self.eta().filter(|eta| eta.as_secs() > 0) {
+            msg = format!(
+                "{} {msg}",
+                style(format!("(ETA {})", format_eta(eta))).dim()
+            );
+        }
+
+        self.progress.set_message(msg);
+        self.last_refresh = Instant::now();
+    }
+
+    /// Show logs of the next running build in the window, going back to logs
+    /// of all builds after the last one
+    fn select_next_build(&mut self) {
+        let next = match self.logs_window.selected_id() {
+            None => self.running_builds.first(),
+            Some(selected) => self
+                .running_builds
+                .get_index_of(&selected)
+                .and_then(|index| self.running_builds.get_index(index + 1)),
+        };
+
+        let next = next.map(|(id, build)| (*id, build.name.clone()));
+        self.logs_window.select(next);
+        self.update_message();
+    }
+
+    /// Estimated time left for the whole group. Builds which were never run
+    /// before are expected to last as long as the average known build.
+    fn eta(&self) -> Option<Duration> {
+        let known: Vec<_> = (self.running_builds.values())
+            .filter_map(|build| build.estimate)
+            .collect();
+
+        let average = known
+            .iter()
+            .sum::<Duration>()
+            .checked_div(known.len().try_into().ok()?)?;
+
+        let remaining: Vec<_> = (self.running_builds.values())
+            .map(|build| {
+                build
+                    .remaining()
+                    .unwrap_or_else(|| average.saturating_sub(build.start.elapsed()))
+            })
+            .collect();
+
+        let [done, expected, running] = self.last_state;
+        let pending = expected.saturating_sub(done + running);
+        let total = remaining.iter().sum::<Duration>() + average.mul_f64(pending as f64);
+        let parallelism = self.running_builds.len().max(1) as f64;
+        let longest = remaining.iter().max().copied().unwrap_or_default();
+        Some(longest.max(total.div_f64(parallelism)))
     }
 
     fn build_bar(&self, size: u16) -> MultiBar<'_, 3> {
@@ -79,22 +204,67 @@ impl BuildGroup {
 
 impl Handler for BuildGroup {
     fn on_action(&mut self, state: &mut State, action: &Action) -> anyhow::Result<HandlerResult> {
+        if self.last_refresh.elapsed() >= ETA_REFRESH
+            && self
+                .running_builds
+                .values()
+                .any(|build| build.estimate.is_some())
+        {
+            self.update_message();
+        }
+
         match action {
             // New build
             Action::Start {
-                start_type: StartFields::Build { target, .. },
+                start_type:
+                    StartFields::Build {
+                        target, machine, ..
+                    },
                 id,
                 ..
             } => {
-                self.builds_formatted
-                    .insert(*id, format_short_build_target(target));
+                self.running_builds.insert(
+                    *id,
+                    RunningBuild {
+                        name: format_short_build_target(target),
+                        host: match_machine_host(machine).map(str::to_string),
+                        phase: None,
+                        start: Instant::now(),
+                        estimate: (state.history.as_ref())
+                            .and_then(|history| history.estimate(target)),
+                    },
+                );
 
+                let name = format_short_build_target(target);
+                self.logs_window.add_build(*id, name);
                 self.update_message();
-                state.plug(LogHandler::new(*id).with_logs_window(self.logs_window.clone()));
+                let log_handler = LogHandler::new(*id)
+                    .awaiting_outcome()
+                    .with_logs_window(self.logs_window.clone())
+                    .with_log_file(state, target)?;
+
+                state.plug(log_handler);
+            }
+
+            // Build entered a new phase
+            Action::Result {
+                id,
+                fields: ResultFields::SetPhase(phase),
+            } => {
+                if let Some(build) = self.running_builds.get_mut(id) {
+                    build.phase = Some(phase.to_string());
+                    self.update_message();
+                }
             }
 
             // Stop build
-            Action::Stop { id } if self.builds_formatted.shift_remove(id).is_some() => {
+            Action::Stop { id } if self.running_builds.shift_remove(id).is_some() => {
+                if self.logs_window.selected_id() == Some(*id) {
+                    self.logs_window.select(None);
+                }
+
+                self.logs_window.remove_build(*id);
+
                 self.update_message();
             }
 
@@ -106,10 +276,11 @@ impl Handler for BuildGroup {
                         done,
                         expected,
                         running,
-                        ..
+                        failed,
                     },
             } if *id == self.id => {
                 self.last_state = [*done, *expected, *running];
+                self.nb_failed = *failed;
 
                 self.progress
                     .set_prefix(self.build_bar(state.term_size).to_string());
@@ -121,10 +292,17 @@ impl Handler for BuildGroup {
             // Stop builds
             Action::Stop { id } if *id == self.id => {
                 let nb_built = self.progress.length().unwrap_or(0);
+                let detail = style(format!("({:.0?})", self.progress.duration())).dim();
 
-                if nb_built > 0 {
+                if self.nb_failed > 0 {
+                    let icon = style("⯈").red();
+                    let nb_built = nb_built.saturating_sub(self.nb_failed);
+                    let failed = style(format!("{} failed", self.nb_failed)).red().bold();
+                    state.println(format!(
+                        "{icon} Built {nb_built} derivations, {failed} {detail}"
+                    ))?;
+                } else if nb_built > 0 {
                     let icon = style("⯈").green();
-                    let detail = style(format!("({:.0?})", self.progress.duration())).dim();
                     state.println(format!("{icon} Built {nb_built} derivations {detail}"))?;
                 }
 
@@ -140,7 +318,7 @@ impl Handler for BuildGroup {
     }
 
     fn on_resize(&mut self, state: &mut State) -> anyhow::Result<()> {
-        self.logs_window.resize(state.term_size);
+        self.logs_window.resize(state.term_size, state.term_rows);
         self.progress.set_style(get_style(state.term_size));
 
         self.progress
@@ -148,4 +326,23 @@ impl Handler for BuildGroup {
 
         Ok(())
     }
+
+    fn on_input(&mut self, state: &mut State, input: Input) -> anyhow::Result<()> {
+        match input {
+            Input::ToggleLogsWindow => self.logs_window.toggle_collapsed(),
+            Input::GrowLogsWindow => self.logs_window.grow(),
+            Input::ShrinkLogsWindow => self.logs_window.shrink(),
+            Input::NextBuild => self.select_next_build(),
+            Input::DumpLogs if self.logs_window.selected_id().is_none() => {
+                state.println(format!(
+                    "{} Select a build with {} to show its logs",
+                    style("ℹ").blue(),
+                    style("tab").bold(),
+                ))?;
+            }
+            _ => {}
+        }
+
+        Ok(())
+    }
 }
@@ -1,8 +1,10 @@
 use std::cmp::max;
 
+use console::style;
 use indicatif::{HumanCount, ProgressBar, ProgressFinish, ProgressStyle};
 
 use crate::action::Action;
+use crate::input::Input;
 use crate::state::{Handler, HandlerResult, State};
 use crate::style::template_style;
 
@@ -16,42 +18,90 @@ fn build_style(size: u16) -> ProgressStyle {
 }
 
 pub struct DebugHandler {
-    progress: ProgressBar,
+    progress: Option<ProgressBar>,
     nb_lines: u64,
+    /// Errors that pinix recovered from, which are printed once the debug
+    /// bar is displayed
+    errors: Vec<anyhow::Error>,
 }
 
 impl DebugHandler {
-    pub fn new(state: &mut State) -> Self {
+    pub fn new(
+        state: &mut State,
+        visible: bool,
+        errors: Vec<anyhow::Error>,
+    ) -> anyhow::Result<Self> {
+        let mut handler = DebugHandler {
+            progress: None,
+            nb_lines: 0,
+            errors,
+        };
+
+        if visible {
+            handler.show(state)?;
+        }
+
+        Ok(handler)
+    }
+
+    fn show(&mut self, state: &mut State) -> anyhow::Result<()> {
+        for err in std::mem::take(&mut self.errors) {
+            state.println(format!("{}: {err:#}", style("debug").magenta().bold()))?;
+        }
+
         let progress = ProgressBar::new_spinner()
             .with_style(build_style(state.term_size))
             .with_finish(ProgressFinish::Abandon);
 
-        let progress = state.add(progress);
+        self.progress = Some(state.add(progress));
+        self.update(state);
+        Ok(())
+    }
 
-        DebugHandler {
-            progress,
-            nb_lines: 0,
+    fn update(&self, state: &State) {
+        let Some(progress) = &self.progress else {
+            return;
+        };
+
+        let handlers_len = state.handlers_len as _;
+
+        progress.set_length(max(handlers_len, progress.length().unwrap_or(0)));
+
+        let mut msg = format!("Parsed {} lines of log", HumanCount(self.nb_lines));
+
+        if !state.skipped.is_empty() {
+            msg += &format!(" ({} skipped)", HumanCount(state.skipped.total()));
         }
+
+        progress.set_message(msg);
+        progress.set_position(handlers_len);
     }
 }
 
 impl Handler for DebugHandler {
     fn on_action(&mut self, state: &mut State, _action: &Action) -> anyhow::Result<HandlerResult> {
-        let handlers_len = state.handlers_len as _;
         self.nb_lines += 1;
-
-        self.progress
-            .set_length(max(handlers_len, self.progress.length().unwrap_or(0)));
-
-        self.progress
-            .set_message(format!("Parsed {} lines of log", HumanCount(self.nb_lines)));
-
-        self.progress.set_position(handlers_len);
+        self.update(state);
         Ok(HandlerResult::Continue)
     }
 
     fn on_resize(&mut self, state: &mut State) -> anyhow::Result<()> {
-        self.progress.set_style(build_style(state.term_size));
+        if let Some(progress) = &self.progress {
+            progress.set_style(build_style(state.term_size));
+        }
+
+        Ok(())
+    }
+
+    fn on_input(&mut self, state: &mut State, input: Input) -> anyhow::Result<()> {
+        if input == Input::ToggleDebug {
+            if let Some(progress) = self.progress.take() {
+                progress.finish_and_clear();
+            } else {
+                self.show(state)?;
+            }
+        }
+
         Ok(())
     }
 }
@@ -1,25 +1,36 @@
+use std::time::Instant;
+
 use console::style;
 use indicatif::{HumanBytes, ProgressBar, ProgressStyle};
 use once_cell::sync::Lazy;
 
-use crate::action::{Action, BuildStepId, ResultFields, StartFields};
+use crate::action::{Action, BuildStepId, CopyDirection, ResultFields, StartFields};
 use crate::handlers::logs::LogHandler;
-use crate::state::{Handler, HandlerResult, State};
-use crate::style::{format_build_target, format_short_build_target, template_style, MultiBar};
+use crate::state::{Handler, HandlerResult, Renderer, State};
+use crate::style::{
+    format_build_target, format_short_build_target, short_store_uri, template_style, MultiBar,
+};
 
 static C_RUN: Lazy<String> = Lazy::new(|| style("-").blue().bright().to_string());
 
 /// Min size of the package for a progressbar to be displayed
 const MIN_PROGRESS_PAYLOAD: u64 = 10 * 1024 * 1024; // 1MB
 
-fn get_style(size: u16) -> ProgressStyle {
+fn get_style(size: u16, direction: CopyDirection) -> ProgressStyle {
     template_style(
         size,
         true,
-        |size| match size {
-            0..=50 => "Download {wide_msg}",
-            51..=60 => "Download {wide_msg} {binary_bytes_per_sec:^12}",
-            _ => "Download {wide_msg} {binary_bytes_per_sec:^12} {bytes:^12}",
+        |size| match (direction, size) {
+            (CopyDirection::Download, 0..=50) => "Download {wide_msg}",
+            (CopyDirection::Download, 51..=60) => "Download {wide_msg} {binary_bytes_per_sec:^12}",
+            (CopyDirection::Download, _) => {
+                "Download {wide_msg} {binary_bytes_per_sec:^12} {bytes:^12}"
+            }
+            (CopyDirection::Upload, 0..=50) => "Upload {wide_msg}",
+            (CopyDirection::Upload, 51..=60) => "Upload {wide_msg} {binary_bytes_per_sec:^12}",
+            (CopyDirection::Upload, _) => {
+                "Upload {wide_msg} {binary_bytes_per_sec:^12} {bytes:^12}"
+            }
         },
         |_| "[{prefix}]",
     )
@@ -27,17 +38,31 @@ fn get_style(size: u16) -> ProgressStyle {
 
 pub fn handle_new_download(state: &mut State, action: &Action) -> anyhow::Result<HandlerResult> {
     if let Action::Start {
-        start_type: StartFields::CopyPath { path, .. },
+        start_type:
+            StartFields::CopyPath {
+                path,
+                origin,
+                destination,
+            },
         id,
         ..
     } = action
     {
         state.plug(LogHandler::new(*id));
 
-        state.plug(WaitForTransfer {
-            copy_id: *id,
-            path: path.to_string(),
-        })
+        match CopyDirection::new(origin, destination) {
+            CopyDirection::Download => state.plug(WaitForTransfer {
+                copy_id: *id,
+                path: path.to_string(),
+            }),
+            // Nix reports the progress of uploads on the copy itself
+            CopyDirection::Upload => state.plug(Transfer::new(
+                *id,
+                path.to_string(),
+                CopyDirection::Upload,
+                short_store_uri(destination).to_string(),
+            )),
+        }
     };
 
     Ok(HandlerResult::Continue)
@@ -58,11 +83,12 @@ impl Handler for WaitForTransfer {
                 parent,
                 ..
             } if *parent == self.copy_id => {
-                state.plug(Transfer {
-                    transfer_id: *id,
-                    progress: None,
-                    path: std::mem::take(&mut self.path),
-                });
+                state.plug(Transfer::new(
+                    *id,
+                    std::mem::take(&mut self.path),
+                    CopyDirection::Download,
+                    String::new(),
+                ));
 
                 state.plug(LogHandler::new(*id));
                 Ok(HandlerResult::Close)
@@ -75,17 +101,39 @@ impl Handler for WaitForTransfer {
 /// Keep track of transfer
 struct Transfer {
     transfer_id: BuildStepId,
+    /// Only displayed for big enough payloads
     progress: Option<ProgressBar>,
     path: String,
+    direction: CopyDirection,
+    destination: String,
+    start: Instant,
+    done: u64,
 }
 
 impl Transfer {
+    fn new(
+        transfer_id: BuildStepId,
+        path: String,
+        direction: CopyDirection,
+        destination: String,
+    ) -> Self {
+        Self {
+            transfer_id,
+            progress: None,
+            path,
+            direction,
+            destination,
+            start: Instant::now(),
+            done: 0,
+        }
+    }
+
     fn update_bar(&self, term_size: u16) {
         if let Some(progress) = &self.progress {
             let pos = progress.position();
             let exp = progress.length().unwrap_or(pos);
 
-            progress.set_style(get_style(term_size));
+            progress.set_style(get_style(term_size, self.direction));
             progress.set_prefix(
                 MultiBar([("#", pos), (C_RUN.as_str(), exp - pos)])
                     .scale(u64::from(term_size) / 3)
@@ -102,21 +150,15 @@ impl Handler for Transfer {
                 id,
                 fields: ResultFields::Progress { done, expected, .. },
             } if *id == self.transfer_id => {
-                self.progress = self.progress.take().or_else(|| {
-                    if *expected > 0 {
-                        if *expected >= MIN_PROGRESS_PAYLOAD {
-                            let pb = ProgressBar::new(*expected)
-                                .with_style(get_style(state.term_size))
-                                .with_prefix("Download")
-                                .with_message(format_short_build_target(&self.path));
-                            Some(state.add(pb))
-                        } else {
-                            Some(ProgressBar::hidden())
-                        }
-                    } else {
-                        None
-                    }
-                });
+                self.done = *done;
+
+                if self.progress.is_none() && *expected >= MIN_PROGRESS_PAYLOAD {
+                    let pb = ProgressBar::new(*expected)
+                        .with_style(get_style(state.term_size, self.direction))
+                        .with_message(format_short_build_target(&self.path));
+
+                    self.progress = Some(state.add(pb));
+                }
 
                 if let Some(progress) = &self.progress {
                     progress.set_length(*expected);
@@ -128,25 +170,34 @@ impl Handler for Transfer {
             }
 
             Action::Stop { id } if *id == self.transfer_id => {
-                if state.cmd.args.show_downloads {
-                    if let Some(progress) = &self.progress {
-                        let msg_main = format!(
+                if state.config.summary.download || state.renderer == Renderer::Plain {
+                    let msg_main = match self.direction {
+                        CopyDirection::Download => format!(
                             "{} Downloaded {}",
                             style("⭣").green(),
                             format_build_target(&self.path),
-                        );
-
-                        let msg_stats = style(format!(
-                            " ({}, {:.0?})",
-                            HumanBytes(progress.position()),
-                            progress.duration(),
-                        ))
-                        .dim()
-                        .to_string();
-
-                        state.println(msg_main + &msg_stats)?;
-                        progress.finish_and_clear();
-                    }
+                        ),
+                        CopyDirection::Upload => format!(
+                            "{} Uploaded {} to {}",
+                            style("⭡").green(),
+                            format_build_target(&self.path),
+                            style(&self.destination).magenta(),
+                        ),
+                    };
+
+                    let msg_stats = style(format!(
+                        " ({}, {:.0?})",
+                        HumanBytes(self.done),
+                        self.start.elapsed(),
+                    ))
+                    .dim()
+                    .to_string();
+
+                    state.println(msg_main + &msg_stats)?;
+                }
+
+                if let Some(progress) = &self.progress {
+                    progress.finish_and_clear();
                 }
 
                 self.update_bar(state.term_size);
@@ -159,7 +210,7 @@ impl Handler for Transfer {
 
     fn on_resize(&mut self, state: &mut State) -> anyhow::Result<()> {
         if let Some(progress) = &self.progress {
-            progress.set_style(get_style(state.term_size));
+            progress.set_style(get_style(state.term_size, self.direction));
         }
 
         Ok(())
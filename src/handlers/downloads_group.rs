@@ -1,25 +1,31 @@
 use std::collections::HashMap;
 use std::fmt::Write;
+use std::time::{Duration, Instant};
 
 use console::style;
 use indexmap::IndexMap;
 use indicatif::{HumanBytes, ProgressBar, ProgressStyle};
 use once_cell::sync::Lazy;
 
-use crate::action::{Action, ActionType, BuildStepId, ResultFields, StartFields};
+use crate::action::{Action, ActionType, BuildStepId, CopyDirection, ResultFields, StartFields};
 use crate::state::{Handler, HandlerResult, State};
-use crate::style::{format_short_build_target, template_style, MultiBar};
+use crate::style::{
+    format_count, format_eta, format_short_build_target, short_store_uri, template_style, MultiBar,
+};
+use crate::util::throughput::Throughput;
 
 static C_RUN: Lazy<String> = Lazy::new(|| style("-").blue().bright().to_string());
 
+/// Window over which the download rate is computed
+const THROUGHPUT_WINDOW: Duration = Duration::from_secs(5);
+
 fn get_style(size: u16) -> ProgressStyle {
     template_style(
         size,
         true,
         |size| match size {
             0..=50 => "{wide_msg}",
-            51..=60 => "{wide_msg} {binary_bytes_per_sec:^12}",
-            _ => "{wide_msg} {binary_bytes_per_sec:^12} {bytes:^12}",
+            _ => "{wide_msg} {bytes:^12}",
         },
         |_| "[{prefix}]",
     )
@@ -42,15 +48,57 @@ pub fn handle_new_downloads_group(
     Ok(HandlerResult::Continue)
 }
 
+/// Activity of a single remote store during the copies
+struct StoreStats {
+    start: Instant,
+    last_update: Instant,
+}
+
+impl StoreStats {
+    fn new() -> Self {
+        let now = Instant::now();
+
+        Self {
+            start: now,
+            last_update: now,
+        }
+    }
+}
+
+/// Bytes and throughput for each store, as displayed once the copies are done
+fn format_stores(stats: &IndexMap<String, StoreStats>, bytes: IndexMap<&str, u64>) -> String {
+    bytes
+        .into_iter()
+        .map(|(name, done)| {
+            let elapsed = stats[name].last_update - stats[name].start;
+            let throughput = done as f64 / elapsed.as_secs_f64().max(1e-3);
+
+            format!(
+                "{name} ({}, {}/s)",
+                HumanBytes(done),
+                HumanBytes(throughput as u64)
+            )
+        })
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
 struct DownloadsGroup {
     id: BuildStepId,
     progress: Option<ProgressBar>,
     current_copies: IndexMap<BuildStepId, String>,
+    copy_substituters: HashMap<BuildStepId, String>,
+    transfer_substituters: HashMap<BuildStepId, String>,
+    substituters: IndexMap<String, StoreStats>,
+    upload_destinations: HashMap<BuildStepId, String>,
+    destinations: IndexMap<String, StoreStats>,
+    nb_uploaded: u64,
     state_copy: HashMap<BuildStepId, [u64; 2]>,
     state_transfer: HashMap<BuildStepId, [u64; 2]>,
     state_self: [u64; 2],
     max_copy: u64,
     max_transfer: u64,
+    throughput: Throughput,
 }
 
 impl DownloadsGroup {
@@ -59,11 +107,18 @@ impl DownloadsGroup {
             id,
             progress: None,
             current_copies: IndexMap::new(),
+            copy_substituters: HashMap::new(),
+            transfer_substituters: HashMap::new(),
+            substituters: IndexMap::new(),
+            upload_destinations: HashMap::new(),
+            destinations: IndexMap::new(),
+            nb_uploaded: 0,
             state_copy: HashMap::new(),
             state_transfer: HashMap::new(),
             state_self: [0; 2],
             max_copy: 0,
             max_transfer: 0,
+            throughput: Throughput::new(THROUGHPUT_WINDOW),
         }
     }
 
@@ -79,7 +134,75 @@ impl DownloadsGroup {
     }
 
     fn get_unpacked(&self) -> u64 {
-        self.state_copy.values().map(|&[done, ..]| done).sum()
+        (self.state_copy.iter())
+            .filter(|(id, _)| !self.upload_destinations.contains_key(id))
+            .map(|(_, &[done, ..])| done)
+            .sum()
+    }
+
+    fn get_uploaded(&self) -> u64 {
+        self.get_uploaded_per_destination().values().sum()
+    }
+
+    /// Wording for the progress, depending on which direction copies go
+    fn verb(&self) -> &'static str {
+        match (self.substituters.is_empty(), self.destinations.is_empty()) {
+            (true, false) => "Uploaded",
+            (false, false) => "Copied",
+            _ => "Downloaded",
+        }
+    }
+
+    /// Register activity of the substituter serving a transfer
+    fn touch_substituter(&mut self, transfer_id: BuildStepId) {
+        if let Some(substituter) = (self.transfer_substituters.get(&transfer_id))
+            .and_then(|name| self.substituters.get_mut(name))
+        {
+            substituter.last_update = Instant::now();
+        }
+    }
+
+    /// Register activity of the destination of an upload
+    fn touch_destination(&mut self, copy_id: BuildStepId) {
+        if let Some(destination) = (self.upload_destinations.get(&copy_id))
+            .and_then(|name| self.destinations.get_mut(name))
+        {
+            destination.last_update = Instant::now();
+        }
+    }
+
+    /// Bytes uploaded to each destination
+    fn get_uploaded_per_destination(&self) -> IndexMap<&str, u64> {
+        let mut result: IndexMap<_, _> = (self.destinations.keys())
+            .map(|name| (name.as_str(), 0))
+            .collect();
+
+        for (id, name) in &self.upload_destinations {
+            if let (Some(&[done, _]), Some(total)) =
+                (self.state_copy.get(id), result.get_mut(name.as_str()))
+            {
+                *total += done;
+            }
+        }
+
+        result
+    }
+
+    /// Bytes downloaded from each substituter
+    fn get_done_per_substituter(&self) -> IndexMap<&str, u64> {
+        let mut result: IndexMap<_, _> = (self.substituters.keys())
+            .map(|name| (name.as_str(), 0))
+            .collect();
+
+        for (id, name) in &self.transfer_substituters {
+            if let (Some(&[done, _]), Some(total)) =
+                (self.state_transfer.get(id), result.get_mut(name.as_str()))
+            {
+                *total += done;
+            }
+        }
+
+        result
     }
 
     fn update_bar(&self, term_size: u16) {
@@ -115,11 +238,38 @@ impl DownloadsGroup {
             .join(", ");
 
         let mut msg = format!(
-            "Downloaded {}/{}",
+            "{} {}/{}",
+            self.verb(),
             style(self.state_self[0]).green(),
             self.state_self[1],
         );
 
+        let now = Instant::now();
+
+        if let Some(rate) = self.throughput.rate(now) {
+            let mut speed = format!("{}/s", HumanBytes(rate as u64));
+
+            if let Some(eta) =
+                (self.throughput.eta(now, self.max_transfer)).filter(|eta| eta.as_secs() > 0)
+            {
+                write!(&mut speed, ", ETA {}", format_eta(eta)).unwrap();
+            }
+
+            write!(&mut msg, " {}", style(format!("[{speed}]")).dim()).unwrap();
+        }
+
+        // Only split the progress when there is something to compare
+        if self.substituters.len() > 1 {
+            let per_substituter = self
+                .get_done_per_substituter()
+                .into_iter()
+                .map(|(name, done)| format!("{name} {}", HumanBytes(done)))
+                .collect::<Vec<_>>()
+                .join(", ");
+
+            write!(&mut msg, " {}", style(format!("({per_substituter})")).dim()).unwrap();
+        }
+
         if !pkgs.is_empty() {
             write!(&mut msg, ": {pkgs}").unwrap();
         }
@@ -132,12 +282,32 @@ impl Handler for DownloadsGroup {
     fn on_action(&mut self, state: &mut State, action: &Action) -> anyhow::Result<HandlerResult> {
         match action {
             Action::Start {
-                start_type: StartFields::CopyPath { path, .. },
+                start_type:
+                    StartFields::CopyPath {
+                        path,
+                        origin,
+                        destination,
+                    },
                 id,
                 ..
             } => {
                 self.state_copy.insert(*id, [0; 2]);
 
+                let direction = CopyDirection::new(origin, destination);
+                let store = short_store_uri(direction.remote(origin, destination)).to_string();
+
+                let (copy_stores, stores) = match direction {
+                    CopyDirection::Download => {
+                        (&mut self.copy_substituters, &mut self.substituters)
+                    }
+                    CopyDirection::Upload => {
+                        (&mut self.upload_destinations, &mut self.destinations)
+                    }
+                };
+
+                copy_stores.insert(*id, store.clone());
+                stores.entry(store).or_insert_with(StoreStats::new);
+
                 self.current_copies
                     .insert(*id, format_short_build_target(path));
 
@@ -152,9 +322,14 @@ impl Handler for DownloadsGroup {
             Action::Start {
                 start_type: StartFields::FileTransfer { .. },
                 id,
+                parent,
                 ..
             } => {
                 self.state_transfer.insert(*id, [0; 2]);
+
+                if let Some(substituter) = self.copy_substituters.get(parent) {
+                    self.transfer_substituters.insert(*id, substituter.clone());
+                }
             }
 
             Action::Result {
@@ -168,15 +343,23 @@ impl Handler for DownloadsGroup {
 
                 if let Some(copy) = self.state_copy.get_mut(id) {
                     *copy = [*done, *expected];
+                    self.touch_destination(*id);
                 }
 
                 if let Some(transfer) = self.state_transfer.get_mut(id) {
                     *transfer = [*done, *expected];
+                    self.touch_substituter(*id);
+                    self.throughput.push(Instant::now(), self.get_done());
 
                     if let Some(progress) = &self.progress {
                         progress.set_position(self.get_done());
                         self.update_bar(state.term_size);
                     }
+
+                    // Refresh the rate once there is a count to display along
+                    if self.state_self[1] > 0 {
+                        self.update_message();
+                    }
                 }
             }
 
@@ -208,30 +391,77 @@ impl Handler for DownloadsGroup {
             }
 
             Action::Stop { id } if *id == self.id => {
-                if let Some(progress) = &self.progress {
+                let Some(progress) = &self.progress else {
+                    return Ok(HandlerResult::Close);
+                };
+
+                if !self.substituters.is_empty() || self.destinations.is_empty() {
                     let msg_main = format!(
-                        "{} Downloaded {} derivations",
+                        "{} Downloaded {}",
                         style("⬇").green(),
-                        self.state_self[0],
+                        format_count(
+                            self.state_self[0].saturating_sub(self.nb_uploaded),
+                            "derivation"
+                        ),
                     );
 
-                    let msg_stats = style(format!(
-                        " ({} downloaded / {} unpacked, {:.0?})",
+                    let duration = progress.duration();
+                    let average = self.get_done() as f64 / duration.as_secs_f64().max(1e-3);
+                    let mut rates = format!("avg {}/s", HumanBytes(average as u64));
+
+                    if let Some(peak) = self.throughput.peak() {
+                        write!(&mut rates, ", peak {}/s", HumanBytes(peak as u64)).unwrap();
+                    }
+
+                    let mut msg_stats = style(format!(
+                        " ({} downloaded / {} unpacked, {duration:.0?}, {rates})",
                         HumanBytes(self.get_done()),
                         HumanBytes(self.get_unpacked()),
+                    ))
+                    .dim()
+                    .to_string();
+
+                    if !self.substituters.is_empty() {
+                        let bytes = self.get_done_per_substituter();
+                        let from = format!(" from {}", format_stores(&self.substituters, bytes));
+                        msg_stats += &style(from).dim().to_string();
+                    }
+
+                    state.println(msg_main + &msg_stats)?;
+                }
+
+                if !self.destinations.is_empty() {
+                    let msg_main = format!(
+                        "{} Uploaded {}",
+                        style("⬆").green(),
+                        format_count(self.nb_uploaded, "path"),
+                    );
+
+                    let bytes = self.get_uploaded_per_destination();
+
+                    let msg_stats = style(format!(
+                        " ({} uploaded, {:.0?}) to {}",
+                        HumanBytes(self.get_uploaded()),
                         progress.duration(),
+                        format_stores(&self.destinations, bytes),
                     ))
                     .dim()
                     .to_string();
 
                     state.println(msg_main + &msg_stats)?;
-                    progress.finish_and_clear();
                 }
 
+                progress.finish_and_clear();
                 return Ok(HandlerResult::Close);
             }
 
             Action::Stop { id } => {
+                if self.upload_destinations.contains_key(id) {
+                    self.touch_destination(*id);
+                    self.nb_uploaded += 1;
+                }
+
+                self.touch_substituter(*id);
                 self.current_copies.shift_remove(id);
                 self.update_message();
             }
@@ -0,0 +1,350 @@
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{LineWriter, Write};
+use std::path::Path;
+use std::time::{Duration, Instant};
+
+use anyhow::Context;
+use serde::Serialize;
+
+use crate::action::{Action, BuildStepId, ResultFields, StartFields};
+use crate::state::{Handler, HandlerResult, State};
+use crate::style::{match_build_target, match_machine_host};
+use crate::summary::{BuiltDerivation, Phase, Summary};
+
+/// Minimal interval between two reports of the progress of downloads
+const PROGRESS_INTERVAL: Duration = Duration::from_secs(1);
+
+/// A store path, split into its components when possible
+#[derive(Debug, Serialize)]
+struct StorePath<'a> {
+    path: &'a str,
+    name: Option<&'a str>,
+    version: Option<&'a str>,
+}
+
+impl<'a> StorePath<'a> {
+    fn new(path: &'a str) -> Self {
+        let (name, version) = match match_build_target(path) {
+            Some((_, name, version)) => (Some(name), version),
+            None => (None, None),
+        };
+
+        Self {
+            path,
+            name,
+            version,
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+#[serde(tag = "event", rename_all = "kebab-case")]
+enum Event<'a> {
+    PhaseStarted {
+        phase: Phase,
+    },
+    PhaseFinished {
+        phase: Phase,
+        duration_ms: u128,
+    },
+    BuildStarted {
+        id: u64,
+        derivation: StorePath<'a>,
+        /// Host of the remote builder, absent for local builds
+        #[serde(skip_serializing_if = "Option::is_none")]
+        machine: Option<&'a str>,
+    },
+    /// Build phase that a running build entered
+    Phase {
+        id: u64,
+        phase: &'a str,
+    },
+    /// Nix may report the failure of a build after it stopped, so builds that
+    /// succeeded are only reported once the run is over
+    BuildFinished {
+        id: u64,
+        derivation: StorePath<'a>,
+        duration_ms: u128,
+    },
+    BuildFailed {
+        id: u64,
+        derivation: StorePath<'a>,
+        duration_ms: u128,
+    },
+    DownloadFinished {
+        id: u64,
+        path: StorePath<'a>,
+        substituter: &'a str,
+        bytes: u64,
+        duration_ms: u128,
+    },
+    UploadFinished {
+        id: u64,
+        path: StorePath<'a>,
+        destination: &'a str,
+        bytes: u64,
+        duration_ms: u128,
+    },
+    /// Reported at most once per second, and when all paths are downloaded
+    DownloadProgress {
+        done: u64,
+        expected: u64,
+        running: u64,
+        failed: u64,
+    },
+}
+
+#[derive(Debug, Serialize)]
+struct Record<'a> {
+    time_ms: u128,
+    #[serde(flatten)]
+    event: Event<'a>,
+}
+
+/// Write a stream of normalized events as newline-delimited JSON
+///
+/// Events are derived from the state of the summary, which has already been
+/// updated when an action reaches handlers.
+pub struct EventsHandler {
+    output: Box<dyn Write>,
+    start: Instant,
+    running_phases: HashMap<Phase, Instant>,
+    nb_builds: usize,
+    nb_substituted: usize,
+    nb_uploaded: usize,
+    /// Index of stopped builds in the summary, until their outcome is known
+    pending_builds: Vec<usize>,
+    copies_group: Option<BuildStepId>,
+    copies_progress: [u64; 4],
+    reported_progress: [u64; 4],
+    last_progress_report: Option<Instant>,
+}
+
+impl EventsHandler {
+    /// Write events to given path
+    pub fn new(path: &Path) -> anyhow::Result<Self> {
+        let file =
+            File::create(path).with_context(|| format!("could not open {}", path.display()))?;
+
+        let output = Box::new(LineWriter::new(file));
+
+        Ok(Self {
+            output,
+            start: Instant::now(),
+            running_phases: HashMap::new(),
+            nb_builds: 0,
+            nb_substituted: 0,
+            nb_uploaded: 0,
+            pending_builds: Vec::new(),
+            copies_group: None,
+            copies_progress: [0; 4],
+            reported_progress: [0; 4],
+            last_progress_report: None,
+        })
+    }
+
+    fn emit(&mut self, event: Event) -> anyhow::Result<()> {
+        let record = Record {
+            time_ms: self.start.elapsed().as_millis(),
+            event,
+        };
+
+        serde_json::to_writer(&mut self.output, &record).context("could not serialize event")?;
+        self.output
+            .write_all(b"\n")
+            .context("could not write event")?;
+        self.output.flush().context("could not write event")
+    }
+
+    /// Report phases which started or finished running since last action
+    fn update_phases(&mut self, summary: &Summary) -> anyhow::Result<()> {
+        let running: HashMap<_, _> = summary.running_phases().collect();
+
+        let finished: Vec<_> = (self.running_phases.iter())
+            .filter(|(phase, _)| !running.contains_key(phase))
+            .map(|(phase, since)| (*phase, since.elapsed()))
+            .collect();
+
+        for (phase, duration) in finished {
+            self.emit(Event::PhaseFinished {
+                phase,
+                duration_ms: duration.as_millis(),
+            })?;
+        }
+
+        for phase in summary.running_phases().map(|(phase, _)| phase) {
+            if !self.running_phases.contains_key(&phase) {
+                self.emit(Event::PhaseStarted { phase })?;
+            }
+        }
+
+        self.running_phases = running;
+        Ok(())
+    }
+
+    /// Report the outcome of a stopped build
+    fn emit_build(&mut self, build: &BuiltDerivation, failed: bool) -> anyhow::Result<()> {
+        let id = build.id.into();
+        let derivation = StorePath::new(&build.target);
+        let duration_ms = build.duration.as_millis();
+
+        if failed {
+            self.emit(Event::BuildFailed {
+                id,
+                derivation,
+                duration_ms,
+            })
+        } else {
+            self.emit(Event::BuildFinished {
+                id,
+                derivation,
+                duration_ms,
+            })
+        }
+    }
+
+    /// Report copies which were added to the summary since last action, builds
+    /// are only reported once their outcome is known
+    fn update_finished(&mut self, summary: &Summary) -> anyhow::Result<()> {
+        self.pending_builds
+            .extend(self.nb_builds..summary.builds.len());
+
+        for path in &summary.substituted[self.nb_substituted..] {
+            self.emit(Event::DownloadFinished {
+                id: path.id.into(),
+                path: StorePath::new(&path.path),
+                substituter: &path.substituter,
+                bytes: path.size,
+                duration_ms: path.duration.as_millis(),
+            })?;
+        }
+
+        for path in &summary.uploaded[self.nb_uploaded..] {
+            self.emit(Event::UploadFinished {
+                id: path.id.into(),
+                path: StorePath::new(&path.path),
+                destination: &path.substituter,
+                bytes: path.size,
+                duration_ms: path.duration.as_millis(),
+            })?;
+        }
+
+        self.nb_builds = summary.builds.len();
+        self.nb_substituted = summary.substituted.len();
+        self.nb_uploaded = summary.uploaded.len();
+        self.update_failures(summary)
+    }
+
+    /// Report stopped builds which Nix reported as failed
+    fn update_failures(&mut self, summary: &Summary) -> anyhow::Result<()> {
+        let (failed, pending): (Vec<_>, Vec<_>) = (self.pending_builds.iter())
+            .partition(|&&index| summary.is_failed_step(summary.builds[index].id));
+
+        self.pending_builds = pending;
+
+        for index in failed {
+            self.emit_build(&summary.builds[index], true)?;
+        }
+
+        Ok(())
+    }
+
+    /// Report the progress of downloads, unless it was reported recently
+    fn update_progress(&mut self, force: bool) -> anyhow::Result<()> {
+        let [done, expected, running, failed] = self.copies_progress;
+
+        let recent =
+            (self.last_progress_report).is_some_and(|last| last.elapsed() < PROGRESS_INTERVAL);
+
+        if self.copies_progress == self.reported_progress || (recent && !force && done < expected) {
+            return Ok(());
+        }
+
+        self.reported_progress = self.copies_progress;
+        self.last_progress_report = Some(Instant::now());
+
+        self.emit(Event::DownloadProgress {
+            done,
+            expected,
+            running,
+            failed,
+        })
+    }
+}
+
+impl Handler for EventsHandler {
+    fn on_action(&mut self, state: &mut State, action: &Action) -> anyhow::Result<HandlerResult> {
+        match action {
+            Action::Msg { .. } => {
+                self.update_failures(&state.summary)?;
+            }
+
+            Action::Start { start_type, id, .. } => {
+                self.update_phases(&state.summary)?;
+
+                match start_type {
+                    StartFields::Build {
+                        target, machine, ..
+                    } => {
+                        self.emit(Event::BuildStarted {
+                            id: **id,
+                            derivation: StorePath::new(target),
+                            machine: match_machine_host(machine),
+                        })?;
+                    }
+
+                    StartFields::CopyPaths => {
+                        self.copies_group = Some(*id);
+                    }
+
+                    _ => {}
+                }
+            }
+
+            Action::Result {
+                id,
+                fields: ResultFields::SetPhase(phase),
+            } => {
+                self.emit(Event::Phase { id: **id, phase })?;
+            }
+
+            Action::Result {
+                id,
+                fields:
+                    ResultFields::Progress {
+                        done,
+                        expected,
+                        running,
+                        failed,
+                    },
+            } if self.copies_group == Some(*id) => {
+                self.copies_progress = [*done, *expected, *running, *failed];
+                self.update_progress(false)?;
+            }
+
+            Action::Stop { id } => {
+                if self.copies_group == Some(*id) {
+                    self.update_progress(true)?;
+                    self.copies_group = None;
+                }
+
+                self.update_finished(&state.summary)?;
+                self.update_phases(&state.summary)?;
+            }
+
+            _ => {}
+        }
+
+        Ok(HandlerResult::Continue)
+    }
+
+    fn on_finish(&mut self, state: &mut State) -> anyhow::Result<()> {
+        for index in std::mem::take(&mut self.pending_builds) {
+            let build = &state.summary.builds[index];
+            self.emit_build(build, state.summary.is_failed_step(build.id))?;
+        }
+
+        Ok(())
+    }
+}
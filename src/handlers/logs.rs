@@ -1,32 +1,192 @@
+use std::cell::{Cell, RefCell};
+use std::collections::VecDeque;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
 use std::rc::Rc;
 
+use anyhow::Context;
 use console::style;
-use indicatif::ProgressBar;
+use indexmap::IndexMap;
+use indicatif::{MultiProgress, ProgressBar};
 
 use crate::action::{Action, BuildStepId, ResultFields};
+use crate::input::Input;
 use crate::state::{Handler, HandlerResult, State};
 use crate::style::template_style;
+use crate::wrapper::config::LogWindowMode;
 
 #[derive(Default)]
 pub struct LogHandler {
     id: BuildStepId,
-    logs: Vec<String>,
+    logs: VecDeque<String>,
+    nb_dropped: usize,
     logs_window: Option<Rc<LogsWindow>>,
+    log_file: Option<LogFile>,
+    phase: Option<String>,
+    /// Wait for Nix to report whether the build failed before printing logs
+    await_outcome: bool,
+    stopped: bool,
+}
+
+/// File where all lines of log of a build are written
+struct LogFile {
+    path: PathBuf,
+    writer: BufWriter<File>,
+    nb_lines: usize,
+}
+
+impl LogFile {
+    fn create(dir: &Path, target: &str) -> anyhow::Result<Self> {
+        std::fs::create_dir_all(dir)
+            .with_context(|| format!("could not create {}", dir.display()))?;
+
+        let path = dir.join(log_file_name(target));
+
+        let file =
+            File::create(&path).with_context(|| format!("could not create {}", path.display()))?;
+
+        Ok(Self {
+            path,
+            writer: BufWriter::new(file),
+            nb_lines: 0,
+        })
+    }
+}
+
+/// Name of the log file of a derivation, which is the name of the
+/// derivation itself as builds of the same package may run together
+fn log_file_name(target: &str) -> String {
+    let file_name = target.rsplit('/').next().unwrap_or(target);
+    format!("{}.log", file_name.trim_end_matches(".drv"))
 }
 
 impl LogHandler {
     pub fn new(id: BuildStepId) -> Self {
         Self {
             id,
-            logs: Vec::new(),
+            logs: VecDeque::new(),
+            nb_dropped: 0,
             logs_window: None,
+            log_file: None,
+            phase: None,
+            await_outcome: false,
+            stopped: false,
         }
     }
 
+    /// Only print logs once the outcome of the build is known, as Nix may
+    /// report its failure after it stopped
+    pub fn awaiting_outcome(mut self) -> Self {
+        self.await_outcome = true;
+        self
+    }
+
     pub fn with_logs_window(mut self, logs_window: Rc<LogsWindow>) -> Self {
         self.logs_window = Some(logs_window);
         self
     }
+
+    /// Write all lines of log to a file, if a directory is configured for
+    /// them. Logs are still displayed if the file can't be created.
+    pub fn with_log_file(mut self, state: &State, target: &str) -> anyhow::Result<Self> {
+        let Some(dir) = &state.config.log_files.dir else {
+            return Ok(self);
+        };
+
+        match LogFile::create(dir, target) {
+            Ok(log_file) => self.log_file = Some(log_file),
+            Err(err) => warn_log_file(state, &err)?,
+        }
+
+        Ok(self)
+    }
+
+    fn write_log_file(&mut self, state: &State, msg: &str) -> anyhow::Result<()> {
+        let Some(log_file) = &mut self.log_file else {
+            return Ok(());
+        };
+
+        log_file.nb_lines += 1;
+
+        if let Err(err) = writeln!(log_file.writer, "{msg}") {
+            let err = anyhow::Error::new(err)
+                .context(format!("could not write {}", log_file.path.display()));
+
+            self.log_file = None;
+            warn_log_file(state, &err)?;
+        }
+
+        Ok(())
+    }
+
+    /// Close the log file once the outcome of the build is known, it is
+    /// removed if it is empty or if the build succeeded and its logs must not
+    /// be kept
+    fn close_log_file(&mut self, state: &State, failed: bool) -> anyhow::Result<()> {
+        let Some(mut log_file) = self.log_file.take() else {
+            return Ok(());
+        };
+
+        if log_file.nb_lines == 0 || (!failed && !state.config.log_files.keep_successful) {
+            drop(log_file.writer);
+            std::fs::remove_file(&log_file.path).ok();
+            return Ok(());
+        }
+
+        if let Err(err) = log_file.writer.flush() {
+            let err = anyhow::Error::new(err)
+                .context(format!("could not write {}", log_file.path.display()));
+
+            return warn_log_file(state, &err);
+        }
+
+        state.println(format!(
+            "{} Full log saved to {}",
+            style("ℹ").blue(),
+            style(log_file.path.display()).bold(),
+        ))
+    }
+
+    /// Print as many lines of log as configured for the outcome of the build
+    fn print_outcome(&mut self, state: &State, failed: bool) -> anyhow::Result<()> {
+        let max_len = {
+            if failed {
+                state.config.log_history.failure_size
+            } else {
+                state.config.log_history.size
+            }
+        };
+
+        let truncated = self.logs.len().saturating_sub(max_len);
+        self.logs.drain(..truncated);
+        self.nb_dropped += truncated;
+        self.print_logs(state, failed)?;
+        self.close_log_file(state, failed)
+    }
+
+    /// Print kept lines of log, along with the number of lines that were
+    /// dropped before them
+    fn print_logs(&self, state: &State, failed: bool) -> anyhow::Result<()> {
+        let lines: Vec<_> = self.logs.iter().map(String::as_str).collect();
+        print_lines(state, self.nb_dropped, &lines, failed)
+    }
+
+    /// Read back all lines of log written to the file, if there is one
+    fn read_log_file(&mut self) -> Option<String> {
+        let log_file = self.log_file.as_mut()?;
+        log_file.writer.flush().ok()?;
+        std::fs::read_to_string(&log_file.path).ok()
+    }
+
+    /// Check if lines logged during current phase must go to the logs window,
+    /// lines logged outside of any phase are always displayed.
+    fn is_phase_displayed(&self, state: &State) -> bool {
+        match (&self.phase, &state.config.log_window.phases) {
+            (Some(phase), Some(phases)) => phases.contains(phase),
+            _ => true,
+        }
+    }
 }
 
 impl Handler for LogHandler {
@@ -34,97 +194,389 @@ impl Handler for LogHandler {
         match action {
             Action::Result {
                 id,
-                fields: ResultFields::BuildLogLine(msg),
+                fields: ResultFields::BuildLogLine(msg) | ResultFields::PostBuildLogLine(msg),
                 ..
             } if *id == self.id => {
-                self.logs.push(msg.to_string());
+                // Only keep as many lines as could be displayed if the build fails
+                let max_len = std::cmp::max(
+                    state.config.log_history.size,
+                    state.config.log_history.failure_size,
+                );
+
+                self.write_log_file(state, msg)?;
+                self.logs.push_back(msg.to_string());
+
+                while self.logs.len() > max_len {
+                    self.logs.pop_front();
+                    self.nb_dropped += 1;
+                }
 
                 if let Some(logs_window) = &self.logs_window {
-                    logs_window.log(msg.to_string());
+                    if self.is_phase_displayed(state) {
+                        logs_window.log(self.id, msg.to_string());
+                    }
                 }
             }
 
+            Action::Result {
+                id,
+                fields: ResultFields::SetPhase(phase),
+            } if *id == self.id => {
+                self.phase = Some(phase.to_string());
+            }
+
             Action::Stop { id } if *id == self.id => {
-                let truncated = (state.cmd.args.log_history_len)
-                    .map(|max_len| self.logs.len().saturating_sub(max_len))
-                    .unwrap_or(0);
-
-                if truncated > 0 {
-                    self.logs = self.logs.split_off(truncated);
-
-                    state.println(
-                        style(format!(
-                            "┆ ⋅⋅⋅ {}",
-                            style(format!("(skipped {truncated} lines)")).italic()
-                        ))
-                        .dim()
-                        .to_string(),
-                    )?;
-                }
+                // The window may go away before the outcome of the build is known
+                self.logs_window = None;
+                self.stopped = true;
+            }
 
-                let logs_len = self.logs.len();
+            _ => {}
+        }
 
-                for (i, line) in self.logs.iter().enumerate() {
-                    let prefix = if i + 1 == logs_len { '└' } else { '│' };
-                    state.println(style(format!("{prefix} {line}")).dim().to_string())?;
-                }
+        if self.stopped {
+            let failed = state.summary.is_failed_step(self.id);
 
+            if failed || !self.await_outcome {
+                self.print_outcome(state, failed)?;
                 return Ok(HandlerResult::Close);
             }
-
-            _ => {}
         }
 
         Ok(HandlerResult::Continue)
     }
+
+    fn on_finish(&mut self, state: &mut State) -> anyhow::Result<()> {
+        if self.stopped {
+            let failed = state.summary.is_failed_step(self.id);
+            self.print_outcome(state, failed)?;
+        }
+
+        Ok(())
+    }
+
+    fn on_input(&mut self, state: &mut State, input: Input) -> anyhow::Result<()> {
+        let selected =
+            (self.logs_window.as_ref()).and_then(|logs_window| logs_window.selected_name(self.id));
+
+        if let (Input::DumpLogs, Some(name)) = (input, selected) {
+            state.println(format!("{} Logs of {name}", style("ℹ").blue()))?;
+
+            // Only the latest lines are kept in memory
+            match self.read_log_file() {
+                Some(logs) => print_lines(state, 0, &logs.lines().collect::<Vec<_>>(), false)?,
+                None => self.print_logs(state, false)?,
+            }
+        }
+
+        Ok(())
+    }
 }
 
+/// Print lines of log in a frame, preceded by the number of lines that were
+/// dropped before them
+fn print_lines(
+    state: &State,
+    nb_dropped: usize,
+    lines: &[&str],
+    failed: bool,
+) -> anyhow::Result<()> {
+    if nb_dropped > 0 {
+        state.println(
+            style(format!(
+                "┆ ⋅⋅⋅ {}",
+                style(format!("(skipped {nb_dropped} lines)")).italic()
+            ))
+            .dim()
+            .to_string(),
+        )?;
+    }
+
+    for (i, line) in lines.iter().enumerate() {
+        let prefix = if i + 1 == lines.len() { '└' } else { '│' };
+
+        if failed {
+            state.println(format!("{} {line}", style(prefix).red()))?;
+        } else {
+            state.println(style(format!("{prefix} {line}")).dim().to_string())?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Number of lines kept to fill the window when it is resized, or when
+/// another build is selected
+const LOGS_WINDOW_HISTORY: usize = 200;
+
+/// Number of lines added or removed when the window is resized
+const LOGS_WINDOW_STEP: usize = 5;
+
+/// Smallest pane that is displayed for a build, including its label. When
+/// panes of all running builds don't fit, only the most recent one is shown.
+const MIN_PANE_HEIGHT: usize = 3;
+
+/// Latest lines of a build which has its own pane in the window
+struct Pane {
+    name: String,
+    lines: VecDeque<String>,
+    /// Order of the latest line logged by this build
+    last_log: u64,
+}
+
+/// Latest lines of log from all builds, or from a selected one
 pub struct LogsWindow {
-    log_lines: Vec<ProgressBar>,
+    multi_progress: Rc<MultiProgress>,
+    /// Bar displayed right above the window
+    anchor: ProgressBar,
+    mode: LogWindowMode,
+    term_size: Cell<u16>,
+    term_rows: Cell<u16>,
+    size: Cell<usize>,
+    collapsed: Cell<bool>,
+    selected: RefCell<Option<(BuildStepId, String)>>,
+    history: RefCell<VecDeque<(BuildStepId, String)>>,
+    panes: RefCell<IndexMap<BuildStepId, Pane>>,
+    nb_logged: Cell<u64>,
+    log_lines: Rc<RefCell<Vec<ProgressBar>>>,
 }
 
 impl LogsWindow {
-    pub fn new(state: &mut State, after: &ProgressBar) -> Self {
-        let nb_lines = state.cmd.args.log_window_len;
-        let mut log_lines = Vec::new();
+    /// Add `anchor` to the state, the window is displayed right below it
+    pub fn new(state: &mut State, anchor: ProgressBar) -> Self {
+        let log_lines = Rc::new(RefCell::new(Vec::new()));
+        let anchor = state.add_above(anchor, Some(&log_lines));
 
-        for i in 0..nb_lines {
-            let prefix = if i + 1 == nb_lines { "└" } else { "│" };
-            let prev = log_lines.last().unwrap_or(after);
+        let window = Self {
+            multi_progress: state.multi_progress.clone(),
+            anchor,
+            mode: state.config.log_window.mode,
+            term_size: Cell::new(state.term_size),
+            term_rows: Cell::new(state.term_rows),
+            size: Cell::new(state.config.log_window.size),
+            collapsed: Cell::new(false),
+            selected: RefCell::new(None),
+            history: RefCell::new(VecDeque::new()),
+            panes: RefCell::new(IndexMap::new()),
+            nb_logged: Cell::new(0),
+            log_lines,
+        };
 
-            let next = state.multi_progress.insert_after(
-                prev,
-                ProgressBar::new_spinner()
-                    .with_style(template_style(
-                        state.term_size,
-                        false,
-                        |_| style("{prefix} {wide_msg}").dim(),
-                        |_| "",
-                    ))
-                    .with_prefix(prefix),
-            );
+        window.redraw();
+        window
+    }
 
-            log_lines.push(next);
+    pub fn anchor(&self) -> &ProgressBar {
+        &self.anchor
+    }
+
+    pub fn log(&self, id: BuildStepId, msg: String) {
+        self.nb_logged.set(self.nb_logged.get() + 1);
+
+        if let Some(pane) = self.panes.borrow_mut().get_mut(&id) {
+            pane.lines.push_back(msg.clone());
+            pane.last_log = self.nb_logged.get();
+
+            if pane.lines.len() > LOGS_WINDOW_HISTORY {
+                pane.lines.pop_front();
+            }
+        }
+
+        let mut history = self.history.borrow_mut();
+        history.push_back((id, msg));
+
+        if history.len() > LOGS_WINDOW_HISTORY {
+            history.pop_front();
+        }
+
+        drop(history);
+
+        if self.mode == LogWindowMode::PerBuild
+            || self.selected_id().is_none_or(|selected| selected == id)
+        {
+            self.redraw();
+        }
+    }
+
+    /// Give a pane to a build that just started, if builds have their own
+    pub fn add_build(&self, id: BuildStepId, name: String) {
+        if self.mode != LogWindowMode::PerBuild {
+            return;
         }
 
-        Self { log_lines }
+        let pane = Pane {
+            name,
+            lines: VecDeque::new(),
+            last_log: 0,
+        };
+
+        self.panes.borrow_mut().insert(id, pane);
+        self.redraw();
     }
 
-    pub fn log(&self, msg: String) {
-        for (prev, next) in self.log_lines.iter().zip(&self.log_lines[1..]) {
-            if !next.message().is_empty() {
-                prev.set_message(next.message());
-            }
+    pub fn remove_build(&self, id: BuildStepId) {
+        if self.panes.borrow_mut().shift_remove(&id).is_some() {
+            self.redraw();
+        }
+    }
+
+    /// Only display logs of given build, or of all builds if `None`
+    pub fn select(&self, build: Option<(BuildStepId, String)>) {
+        *self.selected.borrow_mut() = build;
+        self.redraw();
+    }
+
+    pub fn selected_id(&self) -> Option<BuildStepId> {
+        self.selected.borrow().as_ref().map(|(id, _)| *id)
+    }
+
+    /// Name of the build if it is the one selected
+    pub fn selected_name(&self, id: BuildStepId) -> Option<String> {
+        (self.selected.borrow().as_ref())
+            .filter(|(selected, _)| *selected == id)
+            .map(|(_, name)| name.clone())
+    }
+
+    pub fn toggle_collapsed(&self) {
+        self.collapsed.set(!self.collapsed.get());
+        self.redraw();
+    }
+
+    pub fn grow(&self) {
+        self.collapsed.set(false);
+        self.size.set(self.size.get() + LOGS_WINDOW_STEP);
+        self.redraw();
+    }
+
+    pub fn shrink(&self) {
+        self.size
+            .set(self.size.get().saturating_sub(LOGS_WINDOW_STEP));
+        self.redraw();
+    }
+
+    /// Prefix and content of each line of the window
+    pub(crate) fn rows(&self) -> Vec<(&'static str, String)> {
+        if self.collapsed.get() {
+            return Vec::new();
+        }
+
+        match self.mode {
+            LogWindowMode::Shared => self.shared_rows(),
+            LogWindowMode::PerBuild => self.pane_rows(),
+        }
+    }
+
+    /// Latest lines at the bottom of the window
+    fn shared_rows(&self) -> Vec<(&'static str, String)> {
+        let size = self.size.get();
+        let selected = self.selected_id();
+        let history = self.history.borrow();
+
+        let mut messages: Vec<_> = (history.iter().rev())
+            .filter(|(id, _)| selected.is_none_or(|selected| selected == *id))
+            .take(size)
+            .map(|(_, msg)| msg.clone())
+            .collect();
+
+        messages.resize(size, String::new());
+        messages.reverse();
+        with_prefixes(messages)
+    }
+
+    /// A labelled pane for each running build, which share the size of the
+    /// window. The window doesn't take more than half of the terminal.
+    fn pane_rows(&self) -> Vec<(&'static str, String)> {
+        let panes = self.panes.borrow();
+        let height = (self.size.get()).min(usize::from(self.term_rows.get()) / 2);
+
+        let mut shown: Vec<_> = match self.selected_id() {
+            Some(selected) => panes.get(&selected).into_iter().collect(),
+            None => panes.values().collect(),
+        };
+
+        if shown.is_empty() || height == 0 {
+            return Vec::new();
+        }
+
+        if height / shown.len() < MIN_PANE_HEIGHT {
+            shown = (shown.into_iter())
+                .max_by_key(|pane| pane.last_log)
+                .into_iter()
+                .collect();
+        }
+
+        let pane_height = height / shown.len();
+        let mut rows = Vec::with_capacity(height);
+
+        for pane in shown {
+            rows.push(("┌", style(&pane.name).bold().to_string()));
+
+            let nb_lines = pane_height - 1;
+            let skipped = pane.lines.len().saturating_sub(nb_lines);
+            let mut lines: Vec<_> = pane.lines.iter().skip(skipped).cloned().collect();
+            lines.resize(nb_lines, String::new());
+            rows.extend(with_prefixes(lines));
         }
 
-        if let Some(last_line) = self.log_lines.last() {
-            last_line.set_message(msg);
+        rows
+    }
+
+    /// Display current rows, adding or removing bars to match their number
+    fn redraw(&self) {
+        let rows = self.rows();
+        let mut log_lines = self.log_lines.borrow_mut();
+
+        while log_lines.len() > rows.len() {
+            let line = log_lines.pop().expect("no line in logs window");
+            line.finish_and_clear();
+            self.multi_progress.remove(&line);
+        }
+
+        while log_lines.len() < rows.len() {
+            let prev = log_lines.last().unwrap_or(&self.anchor);
+
+            let next = self.multi_progress.insert_after(
+                prev,
+                ProgressBar::new_spinner().with_style(template_style(
+                    self.term_size.get(),
+                    false,
+                    |_| style("{prefix} {wide_msg}").dim(),
+                    |_| "",
+                )),
+            );
+
+            log_lines.push(next);
+        }
+
+        for (line, (prefix, msg)) in log_lines.iter().zip(rows) {
+            line.set_prefix(prefix);
+            line.set_message(msg);
         }
     }
 
-    pub fn resize(&self, _size: u16) {
-        for line in &self.log_lines {
+    pub fn resize(&self, size: u16, rows: u16) {
+        self.term_size.set(size);
+        self.term_rows.set(rows);
+        self.redraw();
+
+        for line in self.log_lines.borrow().iter() {
             line.tick();
         }
     }
 }
+
+/// Draw a frame on the left of lines of log
+fn with_prefixes(lines: Vec<String>) -> Vec<(&'static str, String)> {
+    let nb_lines = lines.len();
+
+    (lines.into_iter().enumerate())
+        .map(|(i, line)| (if i + 1 == nb_lines { "└" } else { "│" }, line))
+        .collect()
+}
+
+fn warn_log_file(state: &State, err: &anyhow::Error) -> anyhow::Result<()> {
+    state.println(format!(
+        "{}: could not save build log ({err:#})",
+        style("warning").yellow().bold(),
+    ))
+}
@@ -1,5 +1,8 @@
-use crate::action::Action;
+use console::style;
+
+use crate::action::{Action, ResultFields};
 use crate::state::{HandlerResult, State};
+use crate::style::format_build_target;
 
 pub fn handle_new_message(state: &mut State, action: &Action) -> anyhow::Result<HandlerResult> {
     if let Action::Msg { msg, .. } = action {
@@ -8,3 +11,26 @@ pub fn handle_new_message(state: &mut State, action: &Action) -> anyhow::Result<
 
     Ok(HandlerResult::Continue)
 }
+
+pub fn handle_path_warning(state: &mut State, action: &Action) -> anyhow::Result<HandlerResult> {
+    let (kind, path) = match action {
+        Action::Result {
+            fields: ResultFields::CorruptedPath(path),
+            ..
+        } => ("Corrupted", path),
+        Action::Result {
+            fields: ResultFields::UntrustedPath(path),
+            ..
+        } => ("Untrusted", path),
+        _ => return Ok(HandlerResult::Continue),
+    };
+
+    state.println(format!(
+        "{} {} path {}",
+        style("⚠").red().bold(),
+        style(kind).red().bold(),
+        format_build_target(path),
+    ))?;
+
+    Ok(HandlerResult::Continue)
+}
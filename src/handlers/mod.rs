@@ -1,8 +1,13 @@
 pub mod build;
+pub mod build_waiting;
 pub mod builds_group;
 pub mod debug;
 pub mod download;
 pub mod downloads_group;
+pub mod events;
 pub mod logs;
 pub mod message;
+pub mod optimise;
+pub mod plain;
+pub mod post_build_hook;
 pub mod unknown;
@@ -0,0 +1,107 @@
+use std::time::Duration;
+
+use console::style;
+use indicatif::{HumanBytes, HumanCount, ProgressBar, ProgressFinish, ProgressStyle};
+
+use crate::action::{Action, BuildStepId, ResultFields, StartFields};
+use crate::state::{Handler, HandlerResult, State};
+use crate::style::template_style;
+use crate::util::indicatif_ext::ProgressBarExt;
+
+fn get_style(size: u16) -> ProgressStyle {
+    template_style(size, true, |_| "Optimise {wide_msg} {spinner}", |_| "").tick_chars("…  ")
+}
+
+pub fn handle_new_optimise(state: &mut State, action: &Action) -> anyhow::Result<HandlerResult> {
+    if let Action::Start {
+        start_type: StartFields::OptimiseStore,
+        id,
+        ..
+    } = action
+    {
+        let handler = Optimise::new(*id, state);
+        state.plug(handler);
+    }
+
+    Ok(HandlerResult::Continue)
+}
+
+/// Keep track of bytes saved by hard-linking identical files in the store
+struct Optimise {
+    id: BuildStepId,
+    progress: ProgressBar,
+    nb_linked: u64,
+    bytes_saved: u64,
+}
+
+impl Optimise {
+    fn new(id: BuildStepId, state: &mut State) -> Self {
+        let progress = ProgressBar::new_spinner()
+            .with_style(get_style(state.term_size))
+            .with_finish(ProgressFinish::AndClear);
+
+        let progress = state.add(progress);
+        progress.spawn_steady_tick(Duration::from_secs(1));
+
+        let handler = Self {
+            id,
+            progress,
+            nb_linked: 0,
+            bytes_saved: 0,
+        };
+
+        handler.update_message();
+        handler
+    }
+
+    fn update_message(&self) {
+        self.progress.set_message(format!(
+            "saved {} by linking {} files",
+            style(HumanBytes(self.bytes_saved)).green(),
+            HumanCount(self.nb_linked),
+        ));
+    }
+}
+
+impl Handler for Optimise {
+    fn on_action(&mut self, state: &mut State, action: &Action) -> anyhow::Result<HandlerResult> {
+        match action {
+            Action::Result {
+                id,
+                fields: ResultFields::FileLinked { size, .. },
+            } if *id == self.id => {
+                self.nb_linked += 1;
+                self.bytes_saved += size;
+                self.update_message();
+            }
+
+            Action::Stop { id } if *id == self.id => {
+                let icon = style("✓").green();
+
+                let detail = style(format!(
+                    "({} files, {:.0?})",
+                    HumanCount(self.nb_linked),
+                    self.progress.elapsed()
+                ))
+                .dim();
+
+                state.println(format!(
+                    "{icon} Optimised store, saved {} {detail}",
+                    HumanBytes(self.bytes_saved)
+                ))?;
+
+                self.progress.finish_and_clear();
+                return Ok(HandlerResult::Close);
+            }
+
+            _ => {}
+        }
+
+        Ok(HandlerResult::Continue)
+    }
+
+    fn on_resize(&mut self, state: &mut State) -> anyhow::Result<()> {
+        self.progress.set_style(get_style(state.term_size));
+        Ok(())
+    }
+}
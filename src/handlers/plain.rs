@@ -0,0 +1,198 @@
+use std::collections::HashSet;
+use std::time::{Duration, Instant};
+
+use console::style;
+use indicatif::HumanBytes;
+
+use crate::action::{Action, ActionType, BuildStepId, CopyDirection, ResultFields, StartFields};
+use crate::state::{Handler, HandlerResult, State};
+use crate::style::{format_build_target, match_machine_host, short_store_uri};
+
+/// Print progress as plain lines of log, this handler is only plugged when
+/// progress bars can't be displayed.
+pub struct PlainRenderer {
+    interval: Duration,
+    last_report: Instant,
+    builds_group: Option<BuildStepId>,
+    copies_group: Option<BuildStepId>,
+    builds: [u64; 3],
+    copies: [u64; 3],
+    bytes_expected: u64,
+    copy_paths: HashSet<BuildStepId>,
+    has_uploads: bool,
+    transfers: Vec<(BuildStepId, u64)>,
+    bytes_done: u64,
+    changed: bool,
+}
+
+impl PlainRenderer {
+    pub fn new(interval_secs: u64) -> Self {
+        Self {
+            interval: Duration::from_secs(interval_secs),
+            last_report: Instant::now(),
+            builds_group: None,
+            copies_group: None,
+            builds: [0; 3],
+            copies: [0; 3],
+            bytes_expected: 0,
+            copy_paths: HashSet::new(),
+            has_uploads: false,
+            transfers: Vec::new(),
+            bytes_done: 0,
+            changed: false,
+        }
+    }
+
+    fn report(&mut self, state: &State) -> anyhow::Result<()> {
+        self.last_report = Instant::now();
+        self.changed = false;
+        let mut parts = Vec::new();
+
+        if self.builds_group.is_some() && self.builds[1] > 0 {
+            let [done, expected, running] = self.builds;
+            parts.push(format!("built {done}/{expected} ({running} running)"));
+        }
+
+        if self.copies_group.is_some() && self.copies[1] > 0 {
+            let [done, expected, running] = self.copies;
+            let bytes = self.bytes_done + self.transfers.iter().map(|(_, b)| b).sum::<u64>();
+
+            let verb = if self.has_uploads {
+                "copied"
+            } else {
+                "downloaded"
+            };
+
+            parts.push(format!(
+                "{verb} {done}/{expected} ({running} running, {}/{})",
+                HumanBytes(bytes),
+                HumanBytes(self.bytes_expected),
+            ));
+        }
+
+        if parts.is_empty() {
+            return Ok(());
+        }
+
+        state.println(
+            style(format!("… Progress: {}", parts.join(", ")))
+                .dim()
+                .to_string(),
+        )
+    }
+}
+
+impl Handler for PlainRenderer {
+    fn on_action(&mut self, state: &mut State, action: &Action) -> anyhow::Result<HandlerResult> {
+        match action {
+            Action::Start {
+                start_type,
+                id,
+                parent,
+                ..
+            } => match start_type {
+                StartFields::Builds => self.builds_group = Some(*id),
+                StartFields::CopyPaths => self.copies_group = Some(*id),
+                StartFields::Build {
+                    target, machine, ..
+                } => {
+                    let mut line = format!(
+                        "{} Building {}",
+                        style("→").blue(),
+                        format_build_target(target)
+                    );
+
+                    if let Some(host) = match_machine_host(machine) {
+                        line = format!("{line} on {}", style(host).magenta());
+                    }
+
+                    state.println(line)?;
+                }
+                StartFields::CopyPath {
+                    path,
+                    origin,
+                    destination,
+                } => match CopyDirection::new(origin, destination) {
+                    CopyDirection::Download => {
+                        self.copy_paths.insert(*id);
+
+                        state.println(format!(
+                            "{} Downloading {}",
+                            style("→").blue(),
+                            format_build_target(path)
+                        ))?;
+                    }
+                    CopyDirection::Upload => {
+                        self.has_uploads = true;
+
+                        state.println(format!(
+                            "{} Uploading {} to {}",
+                            style("→").blue(),
+                            format_build_target(path),
+                            style(short_store_uri(destination)).magenta(),
+                        ))?;
+                    }
+                },
+                StartFields::FileTransfer { .. } if self.copy_paths.contains(parent) => {
+                    self.transfers.push((*id, 0));
+                }
+                _ => {}
+            },
+
+            Action::Result {
+                id,
+                fields:
+                    ResultFields::Progress {
+                        done,
+                        expected,
+                        running,
+                        ..
+                    },
+            } => {
+                let progress = [*done, *expected, *running];
+
+                if Some(*id) == self.builds_group && self.builds != progress {
+                    self.builds = progress;
+                    self.changed = true;
+                } else if Some(*id) == self.copies_group && self.copies != progress {
+                    self.copies = progress;
+                    self.changed = true;
+                } else if let Some((_, bytes)) = self.transfers.iter_mut().find(|(t, _)| t == id) {
+                    *bytes = *done;
+                    self.changed = true;
+                }
+            }
+
+            Action::Result {
+                fields:
+                    ResultFields::SetExpected {
+                        action: ActionType::FileTransfer,
+                        expected,
+                    },
+                ..
+            } => {
+                self.bytes_expected = *expected;
+            }
+
+            Action::Stop { id } => {
+                if Some(*id) == self.builds_group {
+                    self.builds_group = None;
+                } else if Some(*id) == self.copies_group {
+                    self.copies_group = None;
+                } else if let Some(pos) = self.transfers.iter().position(|(t, _)| t == id) {
+                    self.bytes_done += self.transfers.swap_remove(pos).1;
+                } else {
+                    self.copy_paths.remove(id);
+                }
+            }
+
+            _ => {}
+        }
+
+        if self.changed && self.last_report.elapsed() >= self.interval {
+            self.report(state)?;
+        }
+
+        Ok(HandlerResult::Continue)
+    }
+}
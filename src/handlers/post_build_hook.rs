@@ -0,0 +1,91 @@
+use std::time::Duration;
+
+use console::style;
+use indicatif::{ProgressBar, ProgressFinish, ProgressStyle};
+
+use crate::action::{Action, BuildStepId, StartFields};
+use crate::handlers::logs::LogHandler;
+use crate::state::{Handler, HandlerResult, State};
+use crate::style::{format_build_target, format_short_build_target, template_style};
+use crate::util::indicatif_ext::ProgressBarExt;
+
+fn get_style(size: u16) -> ProgressStyle {
+    template_style(
+        size,
+        true,
+        |_| "Post-build hook {wide_msg} {spinner}",
+        |_| "",
+    )
+    .tick_chars("…  ")
+}
+
+pub fn handle_new_post_build_hook(
+    state: &mut State,
+    action: &Action,
+) -> anyhow::Result<HandlerResult> {
+    if let Action::Start {
+        start_type: StartFields::PostBuildHook { target },
+        id,
+        ..
+    } = action
+    {
+        // The hook handler must be plugged first so that its title is printed
+        // before the logs
+        let handler = PostBuildHook::new(*id, target.to_string(), state);
+        state.plug(handler);
+        state.plug(LogHandler::new(*id));
+    }
+
+    Ok(HandlerResult::Continue)
+}
+
+/// A post-build hook running for a derivation that was just built
+struct PostBuildHook {
+    id: BuildStepId,
+    target: String,
+    progress: ProgressBar,
+}
+
+impl PostBuildHook {
+    fn new(id: BuildStepId, target: String, state: &mut State) -> Self {
+        let progress = ProgressBar::new_spinner()
+            .with_style(get_style(state.term_size))
+            .with_message(format_short_build_target(&target))
+            .with_finish(ProgressFinish::AndClear);
+
+        let progress = state.add(progress);
+        progress.spawn_steady_tick(Duration::from_secs(1));
+
+        Self {
+            id,
+            target,
+            progress,
+        }
+    }
+}
+
+impl Handler for PostBuildHook {
+    fn on_action(&mut self, state: &mut State, action: &Action) -> anyhow::Result<HandlerResult> {
+        match action {
+            Action::Stop { id } if *id == self.id => {
+                let icon = style("↳").green();
+                let detail = style(format!("({:.0?})", self.progress.elapsed())).dim();
+
+                state.println(format!(
+                    "{icon} Ran post-build hook for {} {detail}",
+                    format_build_target(&self.target)
+                ))?;
+
+                self.progress.finish_and_clear();
+                Ok(HandlerResult::Close)
+            }
+
+            _ => Ok(HandlerResult::Continue),
+        }
+    }
+
+    fn on_resize(&mut self, state: &mut State) -> anyhow::Result<()> {
+        self.progress.set_style(get_style(state.term_size));
+        Ok(())
+    }
+}
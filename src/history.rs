@@ -0,0 +1,172 @@
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+use std::time::{Duration, SystemTime};
+
+use anyhow::Context;
+use serde::{Deserialize, Serialize};
+
+use crate::style::match_build_target;
+use crate::summary::BuiltDerivation;
+
+/// Number of past durations kept for each derivation
+const DURATIONS_PER_BUILD: usize = 5;
+
+/// Durations of past builds, which are used to estimate how long builds will
+/// last. Builds are identified by the name and version of their derivation,
+/// so that the estimate holds when a dependency changes.
+#[derive(Debug, Default, Deserialize, Serialize)]
+pub struct BuildHistory {
+    #[serde(skip)]
+    path: PathBuf,
+    builds: BTreeMap<String, PastBuilds>,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+struct PastBuilds {
+    name: String,
+    durations_ms: Vec<u64>,
+    /// Seconds since UNIX epoch
+    last_seen: u64,
+}
+
+impl PastBuilds {
+    fn mean(&self) -> Option<Duration> {
+        let count = u64::try_from(self.durations_ms.len())
+            .ok()
+            .filter(|c| *c > 0)?;
+        let total: u64 = self.durations_ms.iter().sum();
+        Some(Duration::from_millis(total / count))
+    }
+}
+
+impl BuildHistory {
+    /// Path to the history of the user, following XDG conventions
+    pub fn default_path() -> Option<PathBuf> {
+        let cache_home = std::env::var_os("XDG_CACHE_HOME")
+            .filter(|path| !path.is_empty())
+            .map(PathBuf::from)
+            .or_else(|| Some(PathBuf::from(std::env::var_os("HOME")?).join(".cache")))?;
+
+        Some(cache_home.join("pinix").join("build-history.json"))
+    }
+
+    /// History without any build, which is saved to given file
+    pub fn empty(path: PathBuf) -> Self {
+        Self {
+            path,
+            builds: BTreeMap::new(),
+        }
+    }
+
+    /// Read history from a file, which is empty if the file doesn't exist yet
+    pub fn open(path: PathBuf) -> anyhow::Result<Self> {
+        let raw = match std::fs::read_to_string(&path) {
+            Ok(raw) => raw,
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(Self::empty(path)),
+            Err(err) => {
+                return Err(err).with_context(|| format!("could not read {}", path.display()))
+            }
+        };
+
+        let mut history: Self = serde_json::from_str(&raw)
+            .with_context(|| format!("invalid build history in {}", path.display()))?;
+
+        history.path = path;
+        Ok(history)
+    }
+
+    /// Expected duration for building a derivation. If it was never built
+    /// before, the latest version which was built is used instead.
+    pub fn estimate(&self, target: &str) -> Option<Duration> {
+        let (key, name) = build_key(target)?;
+
+        if let Some(past) = self.builds.get(&key) {
+            return past.mean();
+        }
+
+        (self.builds.values())
+            .filter(|past| past.name == name)
+            .max_by_key(|past| past.last_seen)?
+            .mean()
+    }
+
+    /// Add durations of successful builds, keeping at most `max_size`
+    /// derivations among the ones which were built most recently
+    pub fn record(&mut self, builds: &[BuiltDerivation], max_size: usize) {
+        let now = SystemTime::now()
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .map(|elapsed| elapsed.as_secs())
+            .unwrap_or_default();
+
+        for build in builds.iter().filter(|build| !build.failed) {
+            let Some((key, name)) = build_key(&build.target) else {
+                continue;
+            };
+
+            let past = self.builds.entry(key).or_insert_with(|| PastBuilds {
+                name: name.to_string(),
+                durations_ms: Vec::new(),
+                last_seen: now,
+            });
+
+            past.last_seen = now;
+            past.durations_ms.push(build.duration.as_millis() as u64);
+
+            if past.durations_ms.len() > DURATIONS_PER_BUILD {
+                past.durations_ms.remove(0);
+            }
+        }
+
+        if self.builds.len() > max_size {
+            let mut last_seen: Vec<_> = self.builds.values().map(|past| past.last_seen).collect();
+            last_seen.sort_unstable_by(|x, y| y.cmp(x));
+            let oldest_kept = last_seen[max_size.max(1) - 1];
+            self.builds.retain(|_, past| past.last_seen >= oldest_kept);
+        }
+    }
+
+    /// Record builds into the history file. Its latest content is read again
+    /// first, as other runs of pinix may have updated it in the meantime.
+    pub fn save(&self, builds: &[BuiltDerivation], max_size: usize) -> anyhow::Result<()> {
+        if builds.iter().all(|build| build.failed) {
+            return Ok(());
+        }
+
+        // An invalid file is overwritten rather than blocking any update
+        let mut latest =
+            Self::open(self.path.clone()).unwrap_or_else(|_| Self::empty(self.path.clone()));
+
+        latest.record(builds, max_size);
+        latest.write()
+    }
+
+    fn write(&self) -> anyhow::Result<()> {
+        if let Some(parent) = self.path.parent() {
+            std::fs::create_dir_all(parent)
+                .with_context(|| format!("could not create {}", parent.display()))?;
+        }
+
+        let raw = serde_json::to_string(self).context("could not serialize build history")?;
+
+        // Write the whole file at once, so that concurrent runs never read a
+        // partial file
+        let tmp_path = self.path.with_extension("json.tmp");
+        std::fs::write(&tmp_path, raw)
+            .with_context(|| format!("could not write {}", tmp_path.display()))?;
+
+        std::fs::rename(&tmp_path, &self.path)
+            .with_context(|| format!("could not write {}", self.path.display()))
+    }
+}
+
+/// Identify a derivation by its name and version, along with its name alone
+fn build_key(target: &str) -> Option<(String, &str)> {
+    let (_, name, version) = match_build_target(target)?;
+
+    let key = match version {
+        Some(version) => format!("{name}-{version}"),
+        None => name.to_string(),
+    };
+
+    Some((key, name))
+}
@@ -0,0 +1,166 @@
+use std::io::{self, PipeWriter};
+use std::os::fd::AsRawFd;
+use std::thread::JoinHandle;
+
+use anyhow::Context;
+use futures::channel::mpsc;
+use futures::StreamExt;
+
+/// Actions triggered from the keyboard while the command runs
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Input {
+    ToggleLogsWindow,
+    GrowLogsWindow,
+    ShrinkLogsWindow,
+    NextBuild,
+    DumpLogs,
+    ToggleDebug,
+    Help,
+}
+
+/// Keys for each input, along with their description
+pub const KEY_BINDINGS: &[(&str, &str)] = &[
+    ("l", "collapse or expand logs"),
+    ("+/-", "show more or less logs"),
+    ("tab", "select the build to show logs of"),
+    ("s", "show full log of the selected build"),
+    ("d", "toggle debug bar"),
+    ("?", "help"),
+];
+
+impl Input {
+    pub fn from_key(key: u8) -> Option<Self> {
+        match key {
+            b'l' => Some(Self::ToggleLogsWindow),
+            b'+' | b'=' => Some(Self::GrowLogsWindow),
+            b'-' => Some(Self::ShrinkLogsWindow),
+            b'\t' | b'n' => Some(Self::NextBuild),
+            b's' => Some(Self::DumpLogs),
+            b'd' => Some(Self::ToggleDebug),
+            b'?' | b'h' => Some(Self::Help),
+            _ => None,
+        }
+    }
+}
+
+/// Read keys typed in the terminal. The terminal leaves canonical mode while
+/// this is alive, so that keys are received without waiting for a new line,
+/// and it stops echoing them.
+pub struct Keyboard {
+    receiver: mpsc::UnboundedReceiver<Input>,
+    original: libc::termios,
+    /// Closing this pipe wakes the reader up so that it stops
+    stop: Option<PipeWriter>,
+    reader: Option<JoinHandle<()>>,
+}
+
+impl Keyboard {
+    /// Start listening to the keyboard, if stdin is a terminal
+    pub fn open() -> anyhow::Result<Option<Self>> {
+        let fd = io::stdin().as_raw_fd();
+
+        // Safety: isatty has no memory safety requirements
+        if unsafe { libc::isatty(fd) } != 1 {
+            return Ok(None);
+        }
+
+        let (stop_reader, stop) = io::pipe().context("could not create pipe")?;
+
+        // Safety: termios is a plain C struct that is fully initialized by
+        // tcgetattr before being used.
+        let original = unsafe {
+            let mut original = std::mem::zeroed();
+
+            if libc::tcgetattr(fd, &mut original) != 0 {
+                return Err(io::Error::last_os_error()).context("could not read tty attributes");
+            }
+
+            let mut termios = original;
+            termios.c_lflag &= !(libc::ICANON | libc::ECHO);
+            termios.c_cc[libc::VMIN] = 1;
+            termios.c_cc[libc::VTIME] = 0;
+
+            if libc::tcsetattr(fd, libc::TCSANOW, &termios) != 0 {
+                return Err(io::Error::last_os_error()).context("could not set tty attributes");
+            }
+
+            original
+        };
+
+        let (sender, receiver) = mpsc::unbounded();
+
+        // Reading stdin through tokio would prevent the runtime from shutting
+        // down while waiting for a key. The thread is stopped instead before
+        // the terminal is restored, so that it doesn't take keys typed for
+        // whatever runs next.
+        let reader = std::thread::spawn(move || {
+            let mut fds = [fd, stop_reader.as_raw_fd()].map(|fd| libc::pollfd {
+                fd,
+                events: libc::POLLIN,
+                revents: 0,
+            });
+
+            loop {
+                // Safety: fds is an array of initialized pollfd structs
+                let res = unsafe { libc::poll(fds.as_mut_ptr(), fds.len() as libc::nfds_t, -1) };
+
+                if res < 0 && io::Error::last_os_error().kind() == io::ErrorKind::Interrupted {
+                    continue;
+                }
+
+                if res < 0 || fds[1].revents != 0 || fds[0].revents & libc::POLLIN == 0 {
+                    break;
+                }
+
+                let mut keys = [0; 16];
+
+                // Safety: at most the length of the buffer is written to it
+                let len = unsafe { libc::read(fd, keys.as_mut_ptr().cast(), keys.len()) };
+
+                let Ok(len @ 1..) = usize::try_from(len) else {
+                    break;
+                };
+
+                for input in keys[..len].iter().filter_map(|key| Input::from_key(*key)) {
+                    if sender.unbounded_send(input).is_err() {
+                        return;
+                    }
+                }
+            }
+        });
+
+        Ok(Some(Self {
+            receiver,
+            original,
+            stop: Some(stop),
+            reader: Some(reader),
+        }))
+    }
+
+    /// Wait for next input, which never returns if there is no keyboard
+    pub async fn next(keyboard: &mut Option<Self>) -> Input {
+        if let Some(keyboard_inner) = keyboard {
+            if let Some(input) = keyboard_inner.receiver.next().await {
+                return input;
+            }
+
+            // Stdin was closed
+            *keyboard = None;
+        }
+
+        std::future::pending().await
+    }
+}
+
+impl Drop for Keyboard {
+    fn drop(&mut self) {
+        drop(self.stop.take());
+
+        if let Some(reader) = self.reader.take() {
+            reader.join().ok();
+        }
+
+        // Safety: the attributes were read from this terminal
+        unsafe { libc::tcsetattr(io::stdin().as_raw_fd(), libc::TCSANOW, &self.original) };
+    }
+}
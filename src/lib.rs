@@ -1,8 +1,12 @@
 pub mod action;
 pub mod action_raw;
+pub mod capture;
 pub mod handlers;
+pub mod history;
+pub mod input;
 pub mod state;
 pub mod style;
+pub mod summary;
 pub mod wrapper;
 
 #[cfg(test)]
@@ -1,21 +1,35 @@
+use std::borrow::Cow;
+use std::cell::RefCell;
+use std::io::Write;
 use std::rc::Rc;
 use std::time::Instant;
 
 use anyhow::Context;
 use console::style;
-use indicatif::{MultiProgress, ProgressBar, ProgressFinish, ProgressStyle};
-use tokio::io::{AsyncWriteExt, BufWriter};
+use indicatif::{MultiProgress, ProgressBar, ProgressDrawTarget, ProgressFinish, ProgressStyle};
 
 use crate::action::Action;
+use crate::capture::Capture;
 use crate::handlers::build::handle_new_build;
+use crate::handlers::build_waiting::handle_new_build_waiting;
 use crate::handlers::builds_group::handle_new_builds_group;
 use crate::handlers::debug::DebugHandler;
 use crate::handlers::download::handle_new_download;
 use crate::handlers::downloads_group::handle_new_downloads_group;
-use crate::handlers::message::handle_new_message;
+use crate::handlers::events::EventsHandler;
+use crate::handlers::message::{handle_new_message, handle_path_warning};
+use crate::handlers::optimise::handle_new_optimise;
+use crate::handlers::plain::PlainRenderer;
+use crate::handlers::post_build_hook::handle_new_post_build_hook;
 use crate::handlers::unknown::handle_new_unknown;
+use crate::history::BuildHistory;
+use crate::input::{Input, Keyboard, KEY_BINDINGS};
+use crate::style::format_short_build_target;
+use crate::summary::Summary;
 use crate::wrapper::command::NixCommand;
-use crate::wrapper::stream::{MergedStreams, OutputStream};
+use crate::wrapper::config::{Config, OutputFormat, RendererMode};
+use crate::wrapper::record::Recorder;
+use crate::wrapper::stream::{LogSource, OutputStream};
 
 #[derive(Eq, PartialEq)]
 pub enum HandlerResult {
@@ -33,6 +47,15 @@ pub trait Handler {
     fn on_resize(&mut self, _state: &mut State) -> anyhow::Result<()> {
         Ok(())
     }
+
+    fn on_input(&mut self, _state: &mut State, _input: Input) -> anyhow::Result<()> {
+        Ok(())
+    }
+
+    /// Called once the command exited, to report what was still pending
+    fn on_finish(&mut self, _state: &mut State) -> anyhow::Result<()> {
+        Ok(())
+    }
 }
 
 impl<F: FnMut(&mut State, &Action) -> anyhow::Result<HandlerResult>> Handler for F {
@@ -45,11 +68,93 @@ impl<F: FnMut(&mut State, &Action) -> anyhow::Result<HandlerResult>> Handler for
     }
 }
 
+/// Lines of log that could not be handled as expected. The wrapped command
+/// keeps running whatever happens to the UI, so these are counted and
+/// reported instead of aborting.
+#[derive(Debug, Default)]
+pub struct SkippedLines {
+    /// Lines which were not valid UTF-8 and were displayed lossily
+    pub invalid_utf8: u64,
+    /// Lines prefixed with `@nix` which could not be parsed and were displayed
+    /// as raw text
+    pub unparsed: u64,
+    /// Failures of handlers, which are counted once per handler
+    pub failed: u64,
+    /// Last error encountered, kept for the exit summary
+    pub last_error: Option<anyhow::Error>,
+}
+
+impl SkippedLines {
+    pub fn is_empty(&self) -> bool {
+        self.invalid_utf8 == 0 && self.unparsed == 0 && self.failed == 0
+    }
+
+    pub fn add_failure(&mut self, err: anyhow::Error) {
+        self.failed += 1;
+        self.last_error = Some(err);
+    }
+
+    pub fn total(&self) -> u64 {
+        self.invalid_utf8 + self.unparsed + self.failed
+    }
+
+    pub fn summary(&self) -> String {
+        let counts = [
+            (self.invalid_utf8, "non UTF-8"),
+            (self.unparsed, "unparsed"),
+            (self.failed, "failed to display"),
+        ];
+
+        let details: Vec<_> = counts
+            .into_iter()
+            .filter(|(count, _)| *count > 0)
+            .map(|(count, kind)| format!("{count} {kind}"))
+            .collect();
+
+        let mut msg = format!(
+            "{}: some lines of log could not be handled ({})",
+            style("warning").yellow().bold(),
+            details.join(", "),
+        );
+
+        if let Some(err) = &self.last_error {
+            msg.push('\n');
+            msg += &style(format!("last error: {err:#}")).dim().to_string();
+        }
+
+        msg
+    }
+}
+
+/// How the state of the command is displayed
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Renderer {
+    /// Progress bars drawn on a terminal
+    Tty,
+    /// Timestamped lines of log, suited for when stderr is not a terminal
+    Plain,
+}
+
+impl Renderer {
+    pub fn from_mode(mode: RendererMode) -> Self {
+        match mode {
+            RendererMode::Auto if console::Term::stderr().is_term() => Self::Tty,
+            RendererMode::Auto => Self::Plain,
+            RendererMode::Tty => Self::Tty,
+            RendererMode::Plain => Self::Plain,
+        }
+    }
+}
+
 pub struct State<'s> {
     pub cmd: &'s NixCommand,
+    pub config: &'s Config,
+    pub renderer: Renderer,
+    pub start_time: Instant,
     pub multi_progress: Rc<MultiProgress>,
     pub handlers: Vec<Box<dyn Handler + 's>>,
     pub term_size: u16,
+    pub term_rows: u16,
 
     // First displayed line, only appears when other lines do
     separator: Option<ProgressBar>,
@@ -57,50 +162,137 @@ pub struct State<'s> {
     /// Keep track of the handler could while applying them. Usefull for
     /// debugging.
     pub handlers_len: usize,
+
+    /// Lines of log which were skipped or degraded
+    pub skipped: SkippedLines,
+
+    /// Statistics about the whole run
+    pub summary: Summary,
+
+    /// What would have been drawn, if the state doesn't draw on the terminal
+    pub capture: Option<RefCell<Capture>>,
+
+    /// Durations of past builds, if they are tracked for this run
+    pub history: Option<BuildHistory>,
 }
 
+/// Size of the terminal that captured states pretend to draw on
+const CAPTURE_TERM_SIZE: u16 = 80;
+const CAPTURE_TERM_ROWS: u16 = 24;
+
 impl<'s> State<'s> {
-    pub fn new(cmd: &'s NixCommand) -> Self {
-        let (_, term_size) = console::Term::stderr().size();
-        let multi_progress = Rc::new(MultiProgress::default());
+    pub fn new(cmd: &'s NixCommand) -> anyhow::Result<Self> {
+        let (term_rows, term_size) = console::Term::stderr().size();
+        let renderer = Renderer::from_mode(cmd.config.renderer.mode);
+        Self::build(cmd, renderer, (term_size, term_rows), None)
+    }
+
+    /// A state that doesn't draw anything but captures what it would draw on
+    /// a terminal
+    pub fn new_captured(cmd: &'s NixCommand) -> anyhow::Result<Self> {
+        Self::build(
+            cmd,
+            Renderer::Tty,
+            (CAPTURE_TERM_SIZE, CAPTURE_TERM_ROWS),
+            Some(Capture::default()),
+        )
+    }
+
+    fn build(
+        cmd: &'s NixCommand,
+        renderer: Renderer,
+        (term_size, term_rows): (u16, u16),
+        capture: Option<Capture>,
+    ) -> anyhow::Result<Self> {
+        let multi_progress = Rc::new(match renderer {
+            Renderer::Tty if capture.is_none() => MultiProgress::default(),
+            _ => MultiProgress::with_draw_target(ProgressDrawTarget::hidden()),
+        });
+
+        // Errors which are only worth reporting when debugging
+        let mut debug_errors = Vec::new();
+
+        // Replays and captures would record durations which are not real. An
+        // invalid history is replaced once the run is over.
+        let history = Some(())
+            .filter(|_| cmd.config.history.enabled)
+            .filter(|_| capture.is_none() && cmd.args.replay.is_none())
+            .and_then(|_| BuildHistory::default_path())
+            .map(|path| {
+                BuildHistory::open(path.clone()).unwrap_or_else(|err| {
+                    debug_errors.push(err.context("ignored build history"));
+                    BuildHistory::empty(path)
+                })
+            });
 
         let mut state = Self {
             cmd,
+            config: &cmd.config,
+            renderer,
+            start_time: Instant::now(),
             multi_progress,
             handlers: Vec::new(),
             term_size,
+            term_rows,
             separator: None,
             handlers_len: 0,
+            skipped: SkippedLines::default(),
+            summary: Summary::default(),
+            capture: capture.map(RefCell::new),
+            history,
         };
 
-        if cmd.args.debug {
-            let debug_bar = DebugHandler::new(&mut state);
-            state.plug(debug_bar);
-        }
+        // The debug bar can be toggled from the keyboard
+        let debug_bar = DebugHandler::new(&mut state, cmd.config.debug, debug_errors)?;
+        state.plug(debug_bar);
 
         state.plug(handle_new_build);
+        state.plug(handle_new_build_waiting);
         state.plug(handle_new_builds_group);
         state.plug(handle_new_download);
         state.plug(handle_new_downloads_group);
         state.plug(handle_new_message);
+        state.plug(handle_new_optimise);
+        state.plug(handle_new_post_build_hook);
+        state.plug(handle_path_warning);
         state.plug(handle_new_unknown);
-        state
+
+        if state.renderer == Renderer::Plain {
+            let plain = PlainRenderer::new(state.config.renderer.progress_interval);
+            state.plug(plain);
+        }
+
+        if let (OutputFormat::Json, Some(path)) =
+            (state.config.output.format, &state.config.output.path)
+        {
+            let events = EventsHandler::new(path)?;
+            state.plug(events);
+        }
+
+        Ok(state)
     }
 }
 
 impl<'s> State<'s> {
-    pub fn handle(&mut self, action: &Action) -> anyhow::Result<()> {
+    /// Apply an action to all handlers. A handler which fails is counted in
+    /// skipped lines, and doesn't prevent others from handling the action.
+    pub fn handle(&mut self, action: &Action) {
+        self.summary.on_action(action);
+
         // Move out handlers to allow borrowing self
         let mut prev_handlers = std::mem::take(&mut self.handlers);
 
         // Check if terminal was resized
-        let (_, term_size) = console::Term::stderr().size();
+        let (term_rows, term_size) = console::Term::stderr().size();
 
-        if term_size != self.term_size {
+        if (term_size, term_rows) != (self.term_size, self.term_rows) && self.capture.is_none() {
             self.term_size = term_size;
+            self.term_rows = term_rows;
 
             for handler in &mut prev_handlers {
-                handler.on_resize(self)?;
+                if let Err(err) = handler.on_resize(self) {
+                    self.skipped.add_failure(err);
+                }
             }
         }
 
@@ -108,13 +300,11 @@ impl<'s> State<'s> {
             separator.tick();
         }
 
-        // Applies handles
-        let mut retain_result = Ok(());
-
+        // Applies handles, the ones that fail are dropped
         prev_handlers.retain_mut(|h| match h.on_action(self, action) {
             Ok(x) => x == HandlerResult::Continue,
             Err(err) => {
-                retain_result = Err(err);
+                self.skipped.add_failure(err);
                 false
             }
         });
@@ -123,7 +313,119 @@ impl<'s> State<'s> {
         let mut new_handlers = std::mem::replace(&mut self.handlers, prev_handlers);
         self.handlers.append(&mut new_handlers);
         self.handlers_len = self.handlers.len();
-        retain_result
+
+        if let Some(capture) = &self.capture {
+            capture.borrow_mut().snapshot();
+        }
+    }
+
+    /// Apply an input from the keyboard to all handlers
+    pub fn handle_input(&mut self, input: Input) -> anyhow::Result<()> {
+        if input == Input::Help {
+            let bindings: Vec<_> = (KEY_BINDINGS.iter())
+                .map(|(key, help)| format!("{} {help}", style(key).bold()))
+                .collect();
+
+            self.println(format!(
+                "{} Keys: {}",
+                style("ℹ").blue(),
+                bindings.join(", ")
+            ))?;
+        }
+
+        let mut prev_handlers = std::mem::take(&mut self.handlers);
+        let mut result = Ok(());
+
+        for handler in &mut prev_handlers {
+            result = result.and_then(|_| handler.on_input(self, input));
+        }
+
+        let mut new_handlers = std::mem::replace(&mut self.handlers, prev_handlers);
+        self.handlers.append(&mut new_handlers);
+
+        if let Some(capture) = &self.capture {
+            capture.borrow_mut().snapshot();
+        }
+
+        result
+    }
+
+    /// Handle a line of output from the command, which is either displayed
+    /// or parsed as an action
+    pub fn handle_line(&mut self, output: OutputStream, line: &[u8]) -> anyhow::Result<()> {
+        let line = String::from_utf8_lossy(line);
+
+        if let Cow::Owned(_) = line {
+            self.skipped.invalid_utf8 += 1;
+        }
+
+        let line = line.strip_suffix('\n').unwrap_or(&line);
+
+        match output {
+            OutputStream::StdOut => {
+                self.print_output(output, line)?;
+            }
+            OutputStream::StdErr => {
+                if let Some(action_raw) = line.strip_prefix("@nix ") {
+                    match Action::parse(action_raw) {
+                        Ok(action) => self.handle(&action),
+                        Err(err) => {
+                            self.skipped.unparsed += 1;
+                            self.skipped.last_error = Some(err);
+                            self.print_output(output, line)?;
+                        }
+                    }
+                } else {
+                    self.print_output(output, line)?
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Print reports once the command exited, then clear remaining bars
+    pub fn finish(&mut self) -> anyhow::Result<()> {
+        let mut prev_handlers = std::mem::take(&mut self.handlers);
+        let mut result = Ok(());
+
+        for handler in &mut prev_handlers {
+            result = result.and_then(|_| handler.on_finish(self));
+        }
+
+        let mut new_handlers = std::mem::replace(&mut self.handlers, prev_handlers);
+        self.handlers.append(&mut new_handlers);
+        result?;
+
+        if self.config.summary.report {
+            for line in self.summary.report(self.config.summary.report_size) {
+                self.println(line)?;
+            }
+        }
+
+        for build in self.summary.failed_builds() {
+            self.println(format!(
+                "{} Full log for {} is available with {}",
+                style("ℹ").blue(),
+                format_short_build_target(&build.target),
+                style(format!("nix log {}", build.target)).bold(),
+            ))?;
+        }
+
+        if !self.skipped.is_empty() {
+            self.println(self.skipped.summary())?;
+        }
+
+        if let Some(history) = &self.history {
+            if let Err(err) = history.save(&self.summary.builds, self.config.history.size) {
+                self.println(format!(
+                    "{}: could not save build durations ({err:#})",
+                    style("warning").yellow().bold(),
+                ))?;
+            }
+        }
+
+        self.clear()
     }
 
     pub fn plug<H: Handler + 's>(&mut self, handler: H) {
@@ -131,6 +433,16 @@ impl<'s> State<'s> {
     }
 
     pub fn add(&mut self, pb: ProgressBar) -> ProgressBar {
+        self.add_above(pb, None)
+    }
+
+    /// Add a bar right below the separator, `below` are bars which are kept
+    /// right below the new one, such as the lines of a logs window
+    pub fn add_above(
+        &mut self,
+        pb: ProgressBar,
+        below: Option<&Rc<RefCell<Vec<ProgressBar>>>>,
+    ) -> ProgressBar {
         let separator = self.separator.get_or_insert_with(|| {
             let separator = ProgressBar::new_spinner()
                 .with_style(
@@ -138,7 +450,7 @@ impl<'s> State<'s> {
                         .template(&style("·· {prefix} {wide_msg:<}").dim().to_string())
                         .expect("invalid template"),
                 )
-                .with_prefix(format!("Running {}", self.cmd.program.as_str()))
+                .with_prefix(self.cmd.title())
                 .with_message("·".repeat(512))
                 .with_finish(ProgressFinish::AndClear);
 
@@ -147,69 +459,129 @@ impl<'s> State<'s> {
             separator
         });
 
-        self.multi_progress.insert_after(separator, pb)
+        let pb = self.multi_progress.insert_after(separator, pb);
+
+        if let Some(capture) = &self.capture {
+            capture.borrow_mut().track(&pb, below);
+        }
+
+        pb
     }
 
     pub fn remove_separator(&mut self) {
         self.separator.take();
     }
 
-    pub fn println(&self, msg: impl AsRef<str>) -> anyhow::Result<()> {
+    /// Erase all bars that are still displayed and stop drawing, which leaves
+    /// a clean terminal if the command was interrupted before stopping all of
+    /// its activities.
+    pub fn clear(&mut self) -> anyhow::Result<()> {
+        self.separator.take();
+
         self.multi_progress
-            .println(msg)
-            .context("Could not print line")
+            .clear()
+            .context("Could not clear progress bars")?;
+
+        self.multi_progress
+            .set_draw_target(ProgressDrawTarget::hidden());
+
+        Ok(())
+    }
+
+    pub fn println(&self, msg: impl AsRef<str>) -> anyhow::Result<()> {
+        if let Some(capture) = &self.capture {
+            capture.borrow_mut().println(msg.as_ref());
+            return Ok(());
+        }
+
+        match self.renderer {
+            Renderer::Tty => self
+                .multi_progress
+                .println(msg)
+                .context("Could not print line"),
+            Renderer::Plain => {
+                let elapsed = self.start_time.elapsed().as_secs();
+
+                let timestamp = style(format!(
+                    "[{:02}:{:02}:{:02}]",
+                    elapsed / 3600,
+                    (elapsed / 60) % 60,
+                    elapsed % 60,
+                ))
+                .dim();
+
+                let mut stderr = std::io::stderr().lock();
+
+                for line in msg.as_ref().lines() {
+                    writeln!(stderr, "{timestamp} {line}").context("Could not print line")?;
+                }
+
+                Ok(())
+            }
+        }
+    }
+
+    /// Forward a line of output from the command
+    pub fn print_output(&self, output: OutputStream, line: &str) -> anyhow::Result<()> {
+        match (self.renderer, output) {
+            (Renderer::Tty, _) => self.println(line),
+            (Renderer::Plain, OutputStream::StdOut) => {
+                writeln!(std::io::stdout(), "{line}").context("Could not print line")
+            }
+            (Renderer::Plain, OutputStream::StdErr) => {
+                writeln!(std::io::stderr(), "{line}").context("Could not print line")
+            }
+        }
     }
 }
 
 pub async fn monitor_logs(
     cmd: &NixCommand,
-    mut log_stream: MergedStreams<'_>,
+    log_source: &mut LogSource<'_>,
+    mut recorder: Option<&mut Recorder>,
 ) -> anyhow::Result<()> {
-    let mut state = State::new(cmd);
-    let start_time = Instant::now();
+    let mut state = State::new(cmd)?;
 
-    let mut record_file = {
-        if let Some(path) = &cmd.args.record {
-            let file = tokio::fs::File::create(&path)
-                .await
-                .context("could not open record file")?;
-
-            Some(BufWriter::new(file))
+    let mut keyboard = {
+        if cmd.use_keyboard() {
+            Keyboard::open()?
         } else {
             None
         }
     };
 
-    while let Some((output, line)) = log_stream.next_line().await? {
-        let line = std::str::from_utf8(line).context("invalid utf-8")?;
-
-        if let Some(file) = &mut record_file {
-            let elapsed = start_time.elapsed();
-            let line = format!("{} {:07} {line}\n", output.as_str(), elapsed.as_millis());
+    let fed = async {
+        loop {
+            tokio::select! {
+                line = log_source.next_line() => {
+                    let Some((output, line)) = line? else {
+                        break;
+                    };
 
-            file.write_all(line.as_bytes())
-                .await
-                .context("error writing record file")?;
-        }
+                    if let Some(recorder) = &mut recorder {
+                        recorder.write_line(output, line).await?;
+                    }
 
-        match output {
-            OutputStream::StdOut => {
-                state.println(line)?;
-            }
-            OutputStream::StdErr => {
-                if let Some(action_raw) = line.strip_prefix("@nix ") {
-                    let action = Action::parse(action_raw)?;
-                    state.handle(&action)?;
-                } else {
-                    state.println(line)?
+                    state.handle_line(output, line)?;
+                }
+                input = Keyboard::next(&mut keyboard) => {
+                    state.handle_input(input)?;
                 }
             }
         }
+
+        anyhow::Ok(())
     }
+    .await;
+
+    // Give the terminal back before anything else reads from it
+    drop(keyboard);
 
-    if let Some(mut file) = record_file {
-        file.flush().await.context("error saving record file")?;
+    if let Err(err) = fed {
+        // Don't leave progress bars behind the error message
+        state.clear()?;
+        return Err(err);
     }
 
-    Ok(())
+    state.finish()
 }
@@ -1,4 +1,5 @@
 use std::fmt::{self, Display};
+use std::time::Duration;
 
 use console::style;
 use indicatif::ProgressStyle;
@@ -17,7 +18,7 @@ static MATCH_BUILD_TARGET: Lazy<Regex> = Lazy::new(|| {
     .expect("invalid RegEx")
 });
 
-fn match_build_target(raw_str: &str) -> Option<(&str, &str, Option<&str>)> {
+pub fn match_build_target(raw_str: &str) -> Option<(&str, &str, Option<&str>)> {
     let matched = MATCH_BUILD_TARGET.captures(raw_str)?;
 
     Some((
@@ -61,6 +62,40 @@ pub fn format_build_target(raw_str: &str) -> String {
     result
 }
 
+/// Extract the host from the store URI of a remote builder, such as
+/// `ssh-ng://nix@builder.example.com?compress=true`, local builds have no host
+pub fn match_machine_host(uri: &str) -> Option<&str> {
+    let host = uri.split_once("://").map(|(_, rest)| rest).unwrap_or(uri);
+    let host = host.split(['?', '/']).next().unwrap_or(host);
+    let host = host.rsplit_once('@').map(|(_, host)| host).unwrap_or(host);
+    (!host.is_empty()).then_some(host)
+}
+
+/// Short name for a store, such as `cache.nixos.org` for a substituter
+pub fn short_store_uri(uri: &str) -> &str {
+    match_machine_host(uri).unwrap_or(uri)
+}
+
+/// Format a number of items, such as `1 path` or `2 paths`
+pub fn format_count(count: u64, noun: &str) -> String {
+    match count {
+        1 => format!("1 {noun}"),
+        _ => format!("{count} {noun}s"),
+    }
+}
+
+/// Format a duration that is only known approximately, such as `1h05m`,
+/// `3m12s` or `45s`
+pub fn format_eta(duration: Duration) -> String {
+    let secs = duration.as_secs();
+
+    match secs {
+        0..=59 => format!("{secs}s"),
+        60..=3599 => format!("{}m{:02}s", secs / 60, secs % 60),
+        _ => format!("{}h{:02}m", secs / 3600, (secs % 3600) / 60),
+    }
+}
+
 #[derive(Debug)]
 pub struct MultiBar<'s, const N: usize>(pub [(&'s str, u64); N]);
 
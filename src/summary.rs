@@ -0,0 +1,460 @@
+use std::collections::{HashMap, HashSet};
+use std::fmt::{self, Display};
+use std::time::{Duration, Instant};
+
+use console::style;
+use indexmap::IndexMap;
+use indicatif::HumanBytes;
+use once_cell::sync::Lazy;
+use regex::Regex;
+use serde::Serialize;
+
+use crate::action::{Action, BuildStepId, CopyDirection, ResultFields, StartFields};
+use crate::style::{format_short_build_target, match_machine_host};
+
+/// Level of error messages, as defined by Nix's verbosity levels
+const LVL_ERROR: u8 = 0;
+
+static MATCH_FAILED_BUILD: Lazy<Regex> = Lazy::new(|| {
+    Regex::new(r"(?:builder for|Cannot build) '(?P<drv>/nix/store/[^']+\.drv)'")
+        .expect("invalid RegEx")
+});
+
+/// Find the derivation that an error message reports as failed
+pub fn match_failed_build(msg: &str) -> Option<String> {
+    let msg = console::strip_ansi_codes(msg);
+    let matched = MATCH_FAILED_BUILD.captures(&msg)?;
+    Some(matched.name("drv")?.as_str().to_string())
+}
+
+/// Category of activities, time spent in each of them is reported separately
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Hash, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Phase {
+    Evaluation,
+    Download,
+    Build,
+    Optimise,
+    PostBuildHook,
+}
+
+impl Phase {
+    pub fn from_start_fields(fields: &StartFields) -> Option<Self> {
+        match fields {
+            StartFields::Unknown | StartFields::QueryPathInfo => Some(Self::Evaluation),
+            StartFields::CopyPaths
+            | StartFields::CopyPath { .. }
+            | StartFields::FileTransfer { .. }
+            | StartFields::Substitute { .. } => Some(Self::Download),
+            StartFields::Builds | StartFields::Build { .. } => Some(Self::Build),
+            StartFields::OptimiseStore => Some(Self::Optimise),
+            StartFields::PostBuildHook { .. } => Some(Self::PostBuildHook),
+            _ => None,
+        }
+    }
+}
+
+impl Display for Phase {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::Evaluation => "Evaluation",
+            Self::Download => "Download",
+            Self::Build => "Build",
+            Self::Optimise => "Optimise",
+            Self::PostBuildHook => "Post-build hooks",
+        })
+    }
+}
+
+/// Wall-clock time during which at least one activity of a phase was running
+#[derive(Debug, Default)]
+struct PhaseTimer {
+    running: usize,
+    since: Option<Instant>,
+    total: Duration,
+}
+
+impl PhaseTimer {
+    fn start(&mut self, now: Instant) {
+        if self.running == 0 {
+            self.since = Some(now);
+        }
+
+        self.running += 1;
+    }
+
+    fn stop(&mut self, now: Instant) {
+        self.running = self.running.saturating_sub(1);
+
+        if self.running == 0 {
+            if let Some(since) = self.since.take() {
+                self.total += now - since;
+            }
+        }
+    }
+
+    fn elapsed(&self, now: Instant) -> Duration {
+        self.total + self.since.map(|since| now - since).unwrap_or_default()
+    }
+}
+
+#[derive(Debug)]
+pub struct BuiltDerivation {
+    pub id: BuildStepId,
+    pub target: String,
+    /// Host of the remote builder, `None` for local builds
+    pub machine: Option<String>,
+    pub duration: Duration,
+    pub failed: bool,
+}
+
+#[derive(Debug)]
+pub struct SubstitutedPath {
+    pub id: BuildStepId,
+    pub path: String,
+    /// URI of the remote store the path was copied from, or to for uploads
+    pub substituter: String,
+    pub size: u64,
+    pub duration: Duration,
+}
+
+#[derive(Debug)]
+struct RunningBuild {
+    target: String,
+    machine: Option<String>,
+    start: Instant,
+}
+
+#[derive(Debug)]
+struct RunningCopy {
+    path: String,
+    direction: CopyDirection,
+    remote: String,
+    start: Instant,
+    size: u64,
+}
+
+/// Collect statistics about the whole run, which are reported when the
+/// command exits
+#[derive(Debug)]
+pub struct Summary {
+    start: Instant,
+    phases: IndexMap<Phase, PhaseTimer>,
+    activities: HashMap<BuildStepId, Phase>,
+    running_builds: HashMap<BuildStepId, RunningBuild>,
+    running_copies: HashMap<BuildStepId, RunningCopy>,
+    transfers: HashMap<BuildStepId, BuildStepId>,
+    pub builds: Vec<BuiltDerivation>,
+    pub substituted: Vec<SubstitutedPath>,
+    pub uploaded: Vec<SubstitutedPath>,
+    pub failed: u64,
+    failed_builds: HashSet<String>,
+    failed_steps: HashSet<BuildStepId>,
+}
+
+impl Default for Summary {
+    fn default() -> Self {
+        Self {
+            start: Instant::now(),
+            phases: IndexMap::new(),
+            activities: HashMap::new(),
+            running_builds: HashMap::new(),
+            running_copies: HashMap::new(),
+            transfers: HashMap::new(),
+            builds: Vec::new(),
+            substituted: Vec::new(),
+            uploaded: Vec::new(),
+            failed: 0,
+            failed_builds: HashSet::new(),
+            failed_steps: HashSet::new(),
+        }
+    }
+}
+
+impl Summary {
+    pub fn on_action(&mut self, action: &Action) {
+        let now = Instant::now();
+
+        match action {
+            Action::Msg {
+                level: LVL_ERROR,
+                msg,
+            } => {
+                if let Some(target) = match_failed_build(msg) {
+                    // Nix may report the failure after the build was stopped
+                    for build in &mut self.builds {
+                        if build.target == target {
+                            build.failed = true;
+                            self.failed_steps.insert(build.id);
+                        }
+                    }
+
+                    self.failed_builds.insert(target);
+                }
+            }
+
+            Action::Start {
+                start_type,
+                id,
+                parent,
+                ..
+            } => {
+                if let Some(phase) = Phase::from_start_fields(start_type) {
+                    self.activities.insert(*id, phase);
+                    self.phases.entry(phase).or_default().start(now);
+                }
+
+                match start_type {
+                    StartFields::Build {
+                        target, machine, ..
+                    } => {
+                        self.running_builds.insert(
+                            *id,
+                            RunningBuild {
+                                target: target.to_string(),
+                                machine: match_machine_host(machine).map(str::to_string),
+                                start: now,
+                            },
+                        );
+                    }
+                    StartFields::CopyPath {
+                        path,
+                        origin,
+                        destination,
+                    } => {
+                        let direction = CopyDirection::new(origin, destination);
+
+                        self.running_copies.insert(
+                            *id,
+                            RunningCopy {
+                                path: path.to_string(),
+                                direction,
+                                remote: direction.remote(origin, destination).to_string(),
+                                start: now,
+                                size: 0,
+                            },
+                        );
+                    }
+                    StartFields::FileTransfer { .. }
+                        if self.running_copies.contains_key(parent) =>
+                    {
+                        self.transfers.insert(*id, *parent);
+                    }
+                    _ => {}
+                }
+            }
+
+            Action::Result {
+                id,
+                fields: ResultFields::Progress { done, failed, .. },
+            } => {
+                if let Some(copy_id) = self.transfers.get(id) {
+                    if let Some(copy) = self.running_copies.get_mut(copy_id) {
+                        copy.size = *done;
+                    }
+                }
+
+                // Uploads report their progress on the copy itself
+                if let Some(copy) = self.running_copies.get_mut(id) {
+                    if copy.direction == CopyDirection::Upload {
+                        copy.size = *done;
+                    }
+                }
+
+                if self.activities.get(id) == Some(&Phase::Build) {
+                    self.failed = self.failed.max(*failed);
+                }
+            }
+
+            Action::Stop { id } => {
+                if let Some(phase) = self.activities.remove(id) {
+                    self.phases.entry(phase).or_default().stop(now);
+                }
+
+                if let Some(RunningBuild {
+                    target,
+                    machine,
+                    start,
+                }) = self.running_builds.remove(id)
+                {
+                    // A failure that is reported later is attributed once its
+                    // error message is received
+                    let failed = self.failed_builds.contains(&target);
+
+                    if failed {
+                        self.failed_steps.insert(*id);
+                    }
+
+                    self.builds.push(BuiltDerivation {
+                        id: *id,
+                        target,
+                        machine,
+                        duration: now - start,
+                        failed,
+                    });
+                }
+
+                if let Some(copy) = self.running_copies.remove(id) {
+                    let copied = SubstitutedPath {
+                        id: *id,
+                        path: copy.path,
+                        substituter: copy.remote,
+                        size: copy.size,
+                        duration: now - copy.start,
+                    };
+
+                    match copy.direction {
+                        CopyDirection::Download => self.substituted.push(copied),
+                        CopyDirection::Upload => self.uploaded.push(copied),
+                    }
+                }
+
+                self.transfers.remove(id);
+            }
+
+            _ => {}
+        }
+    }
+
+    /// Phases which have running activities, with the time since which they
+    /// are running
+    pub fn running_phases(&self) -> impl Iterator<Item = (Phase, Instant)> + '_ {
+        (self.phases.iter()).filter_map(|(phase, timer)| Some((*phase, timer.since?)))
+    }
+
+    /// Check if a build step was reported as failed, this is unknown until
+    /// Nix prints the error of the build
+    pub fn is_failed_step(&self, id: BuildStepId) -> bool {
+        self.failed_steps.contains(&id)
+    }
+
+    pub fn failed_builds(&self) -> impl Iterator<Item = &BuiltDerivation> {
+        self.builds.iter().filter(|build| build.failed)
+    }
+
+    /// Total build time and number of builds for each machine, local builds
+    /// are grouped under `None`
+    pub fn build_time_per_machine(&self) -> IndexMap<Option<&str>, (Duration, usize)> {
+        let mut machines: IndexMap<_, (Duration, usize)> = IndexMap::new();
+
+        for build in &self.builds {
+            let entry = machines.entry(build.machine.as_deref()).or_default();
+            entry.0 += build.duration;
+            entry.1 += 1;
+        }
+
+        machines.sort_by(|_, (dur_1, _), _, (dur_2, _)| dur_2.cmp(dur_1));
+        machines
+    }
+
+    pub fn total_bytes(&self) -> u64 {
+        self.substituted.iter().map(|path| path.size).sum()
+    }
+
+    /// Lines of the report, which list at most `max_rows` items per table
+    pub fn report(&self, max_rows: usize) -> Vec<String> {
+        let now = Instant::now();
+        let mut lines = Vec::new();
+
+        let title = |title: &str| style(format!("  {title}")).bold().to_string();
+        let duration = |duration: Duration| format!("{:>8}", format!("{duration:.0?}"));
+
+        lines.push(format!(
+            "{} Summary {}",
+            style("≡").blue().bold(),
+            style(format!("({:.0?})", now - self.start)).dim(),
+        ));
+
+        let mut phases: Vec<_> = self
+            .phases
+            .iter()
+            .map(|(phase, timer)| (phase, timer.elapsed(now)))
+            .collect();
+
+        phases.sort_by_key(|(_, elapsed)| std::cmp::Reverse(*elapsed));
+
+        if !phases.is_empty() {
+            lines.push(title("Time per phase"));
+
+            for (phase, elapsed) in phases {
+                lines.push(format!("    {} {phase}", duration(elapsed)));
+            }
+        }
+
+        let mut builds: Vec<_> = self.builds.iter().filter(|build| !build.failed).collect();
+        builds.sort_by_key(|build| std::cmp::Reverse(build.duration));
+
+        if !builds.is_empty() {
+            lines.push(title("Slowest builds"));
+
+            for build in builds.into_iter().take(max_rows) {
+                lines.push(format!(
+                    "    {} {}",
+                    duration(build.duration),
+                    format_short_build_target(&build.target),
+                ));
+            }
+        }
+
+        let machines = self.build_time_per_machine();
+
+        // Only worth a table when some builds were offloaded
+        if machines.keys().any(Option::is_some) {
+            lines.push(title("Build time per machine"));
+
+            for (machine, (elapsed, nb_builds)) in machines {
+                let machine = match machine {
+                    Some(host) => style(host).magenta().to_string(),
+                    None => style("local").dim().to_string(),
+                };
+
+                lines.push(format!(
+                    "    {} {machine} {}",
+                    duration(elapsed),
+                    style(format!("({nb_builds} builds)")).dim(),
+                ));
+            }
+        }
+
+        let mut substituted: Vec<_> = self.substituted.iter().collect();
+        substituted.sort_by_key(|path| std::cmp::Reverse(path.size));
+
+        if !substituted.is_empty() {
+            lines.push(title("Biggest downloads"));
+
+            for path in substituted.into_iter().take(max_rows) {
+                lines.push(format!(
+                    "    {:>10} {} {}",
+                    HumanBytes(path.size).to_string(),
+                    format_short_build_target(&path.path),
+                    style(format!("({:.0?})", path.duration)).dim(),
+                ));
+            }
+        }
+
+        let nb_failed_listed = self.failed_builds().count();
+        let nb_failed = self.failed.max(nb_failed_listed as u64);
+
+        let mut totals = format!(
+            "  {} built, {} downloaded ({})",
+            self.builds.len() - nb_failed_listed,
+            self.substituted.len(),
+            HumanBytes(self.total_bytes()),
+        );
+
+        if !self.uploaded.is_empty() {
+            totals += &format!(
+                ", {} uploaded ({})",
+                self.uploaded.len(),
+                HumanBytes(self.uploaded.iter().map(|path| path.size).sum()),
+            );
+        }
+
+        if nb_failed > 0 {
+            totals += &format!(", {}", style(format!("{nb_failed} failed")).red());
+        }
+
+        lines.push(totals);
+        lines
+    }
+}
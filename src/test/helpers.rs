@@ -0,0 +1,53 @@
+use std::path::{Path, PathBuf};
+
+use clap::Parser;
+
+use crate::wrapper::args::Args;
+use crate::wrapper::command::NixCommand;
+use crate::wrapper::config::Config;
+
+/// Command as pinix would run it, given layers of configuration on top of the
+/// known programs
+pub fn command(layers: Vec<toml::Value>, cmd: &[&str]) -> anyhow::Result<NixCommand> {
+    let (program, args) = cmd.split_first().expect("empty command");
+
+    Ok(NixCommand {
+        program: program.to_string().into(),
+        args: Args::parse_from(["pinix"].iter().chain(args)),
+        config: Config::from_layers(layers)?,
+    })
+}
+
+/// Nix command with a single layer of configuration
+pub fn nix_command(config: &str) -> anyhow::Result<NixCommand> {
+    command(vec![toml::from_str(config)?], &["nix"])
+}
+
+/// Directory which is removed once a test is over, whether it passed or not
+pub struct TempDir {
+    path: PathBuf,
+}
+
+impl TempDir {
+    /// Create an empty directory, which is named after the test using it
+    pub fn new(test: &str) -> anyhow::Result<Self> {
+        let path = std::env::temp_dir().join(format!("pinix-test-{}-{test}", std::process::id()));
+
+        std::fs::create_dir_all(&path)?;
+        Ok(Self { path })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub fn join(&self, path: impl AsRef<Path>) -> PathBuf {
+        self.path.join(path)
+    }
+}
+
+impl Drop for TempDir {
+    fn drop(&mut self) {
+        std::fs::remove_dir_all(&self.path).ok();
+    }
+}
@@ -1,3 +1,12 @@
+pub mod helpers;
 pub mod test_action;
 pub mod test_config;
+pub mod test_history;
+pub mod test_logs_window;
+pub mod test_program;
+pub mod test_record;
+pub mod test_snapshot;
+pub mod test_state;
+pub mod test_summary;
+pub mod test_throughput;
 pub mod test_toml_ext;
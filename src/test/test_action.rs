@@ -1,4 +1,5 @@
 use crate::action::{Action, ActionType, ResultFields, StartFields};
+use crate::style::match_machine_host;
 
 #[test]
 fn parse_start() -> anyhow::Result<()> {
@@ -54,3 +55,172 @@ fn parse_stop() -> anyhow::Result<()> {
 
     Ok(())
 }
+
+#[test]
+fn parse_result_file_linked() -> anyhow::Result<()> {
+    let act =
+        Action::parse(r#"{"action":"result","fields":[4096,8],"id":3239822680391681,"type":100}"#)?;
+
+    assert_eq!(
+        act,
+        Action::Result {
+            id: 3239822680391681.into(),
+            fields: ResultFields::FileLinked {
+                size: 4096,
+                blocks: 8
+            }
+        }
+    );
+
+    Ok(())
+}
+
+#[test]
+fn parse_result_paths() -> anyhow::Result<()> {
+    let path = "/nix/store/8sxmy1pcbb1h0lygzha9gvhpmbmp4qhh-hello-2.12.1";
+
+    let raw = format!(r#"{{"action":"result","fields":["{path}"],"id":1,"type":102}}"#);
+    let act = Action::parse(&raw)?;
+
+    assert_eq!(
+        act,
+        Action::Result {
+            id: 1.into(),
+            fields: ResultFields::UntrustedPath(path.into())
+        }
+    );
+
+    let raw = format!(r#"{{"action":"result","fields":["{path}"],"id":1,"type":103}}"#);
+    let act = Action::parse(&raw)?;
+
+    assert_eq!(
+        act,
+        Action::Result {
+            id: 1.into(),
+            fields: ResultFields::CorruptedPath(path.into())
+        }
+    );
+
+    Ok(())
+}
+
+#[test]
+fn parse_post_build_hook() -> anyhow::Result<()> {
+    let act = Action::parse(concat!(
+        r#"{"action":"start","fields":["/nix/store/ghf3k2vl4c2cr9nfsj3ykbx0a6lspm0f-hello-2.12.1.drv"],"#,
+        r#""id":42,"level":5,"parent":0,"text":"running post-build-hook 'upload'","type":110}"#,
+    ))?;
+
+    assert_eq!(
+        act,
+        Action::Start {
+            start_type: StartFields::PostBuildHook {
+                target: "/nix/store/ghf3k2vl4c2cr9nfsj3ykbx0a6lspm0f-hello-2.12.1.drv".into()
+            },
+            id: 42.into(),
+            level: 5,
+            parent: 0.into(),
+            text: "running post-build-hook 'upload'".into(),
+        }
+    );
+
+    let act = Action::parse(r#"{"action":"result","fields":["uploading..."],"id":42,"type":107}"#)?;
+
+    assert_eq!(
+        act,
+        Action::Result {
+            id: 42.into(),
+            fields: ResultFields::PostBuildLogLine("uploading...".into())
+        }
+    );
+
+    Ok(())
+}
+
+#[test]
+fn parse_invalid_set_expected() {
+    assert!(Action::parse(r#"{"action":"result","fields":["oops"],"id":1,"type":106}"#).is_err());
+}
+
+#[test]
+fn parse_remote_build() -> anyhow::Result<()> {
+    let act = Action::parse(concat!(
+        r#"{"action":"start","fields":["/nix/store/ghf3k2vl4c2cr9nfsj3ykbx0a6lspm0f-hello-2.12.1.drv","#,
+        r#""ssh-ng://nix@builder1.example.com",1,2],"id":7,"level":3,"parent":0,"text":"","type":105}"#,
+    ))?;
+
+    assert_eq!(
+        act,
+        Action::Start {
+            start_type: StartFields::Build {
+                target: "/nix/store/ghf3k2vl4c2cr9nfsj3ykbx0a6lspm0f-hello-2.12.1.drv".into(),
+                machine: "ssh-ng://nix@builder1.example.com".into(),
+                round: 1,
+                nb_rounds: 2,
+            },
+            id: 7.into(),
+            level: 3,
+            parent: 0.into(),
+            text: "".into(),
+        }
+    );
+
+    assert_eq!(
+        match_machine_host("ssh-ng://nix@builder1.example.com?compress=true"),
+        Some("builder1.example.com")
+    );
+
+    assert_eq!(match_machine_host("builder2"), Some("builder2"));
+    assert_eq!(match_machine_host(""), None);
+    Ok(())
+}
+
+#[test]
+fn parse_build_waiting() -> anyhow::Result<()> {
+    let act = Action::parse(
+        r#"{"action":"start","id":8,"level":1,"parent":0,"text":"waiting for a machine","type":111}"#,
+    )?;
+
+    assert_eq!(
+        act,
+        Action::Start {
+            start_type: StartFields::BuildWaiting { machine: None },
+            id: 8.into(),
+            level: 1,
+            parent: 0.into(),
+            text: "waiting for a machine".into(),
+        }
+    );
+
+    let act = Action::parse(
+        r#"{"action":"start","fields":["ssh://builder1"],"id":9,"level":1,"parent":0,"text":"","type":111}"#,
+    )?;
+
+    assert!(matches!(
+        act,
+        Action::Start {
+            start_type: StartFields::BuildWaiting { machine: Some(machine) },
+            ..
+        } if machine == "ssh://builder1"
+    ));
+
+    Ok(())
+}
+
+#[test]
+fn parse_substitute() -> anyhow::Result<()> {
+    let act = Action::parse(concat!(
+        r#"{"action":"start","fields":["/nix/store/wjrndms8j5lc2hnfkvy4ns208w0q42zy-glibc-2.39","#,
+        r#""https://cache.nixos.org"],"id":4,"level":4,"parent":0,"text":"","type":108}"#,
+    ))?;
+
+    assert!(matches!(
+        act,
+        Action::Start {
+            start_type: StartFields::Substitute { source, target },
+            ..
+        } if source == "https://cache.nixos.org" && target.ends_with("glibc-2.39")
+    ));
+
+    Ok(())
+}
@@ -1,6 +1,13 @@
+use std::collections::BTreeMap;
 use std::path::PathBuf;
 
-use crate::wrapper::config::{Config, ConfigLogHistory, ConfigLogWindow, ConfigSummary};
+use clap::Parser;
+
+use crate::wrapper::args::Args;
+use crate::wrapper::config::{
+    Config, ConfigHistory, ConfigLogFiles, ConfigLogHistory, ConfigLogWindow, ConfigOutput,
+    ConfigRenderer, ConfigSummary, LogWindowMode,
+};
 
 #[test]
 fn default_parsing() -> anyhow::Result<()> {
@@ -18,12 +25,27 @@ fn simple_parsing() -> anyhow::Result<()> {
         config,
         Config {
             debug: false,
-            summary: ConfigSummary { download: true },
+            pty: false,
+            keys: None,
+            summary: ConfigSummary {
+                download: true,
+                report: false,
+                report_size: 5,
+            },
             log_history: ConfigLogHistory {
                 size: 5,
                 failure_size: 30
             },
-            log_window: ConfigLogWindow { size: 10 }
+            log_files: ConfigLogFiles::default(),
+            log_window: ConfigLogWindow {
+                size: 10,
+                mode: LogWindowMode::Shared,
+                phases: None,
+            },
+            output: ConfigOutput::default(),
+            renderer: ConfigRenderer::default(),
+            history: ConfigHistory::default(),
+            programs: BTreeMap::new(),
         }
     );
 
@@ -49,3 +71,69 @@ fn unknown_field() -> anyhow::Result<()> {
     assert!(val3.try_into::<Config>().is_err());
     Ok(())
 }
+
+#[test]
+fn layered_parsing() -> anyhow::Result<()> {
+    let path = PathBuf::from(std::env!("CARGO_MANIFEST_DIR")).join("src/test/data/config-1.toml");
+    let file: toml::Table = std::fs::read_to_string(path)?.parse()?;
+
+    let args = Args::parse_from([
+        "pinix",
+        "--pix-debug",
+        "--pix-log-history",
+        "50",
+        "--pix-log-window-mode=per-build",
+        "nix",
+        "--pix-log-window-size",
+        "3",
+    ]);
+
+    let config = Config::from_layers([file.into(), args.as_toml_overrides()])?;
+
+    assert_eq!(
+        config,
+        Config {
+            debug: true,
+            pty: false,
+            keys: None,
+            summary: ConfigSummary {
+                download: true,
+                report: false,
+                report_size: 5,
+            },
+            log_history: ConfigLogHistory {
+                size: 50,
+                failure_size: 30
+            },
+            log_files: ConfigLogFiles::default(),
+            log_window: ConfigLogWindow {
+                size: 10,
+                mode: LogWindowMode::PerBuild,
+                phases: None,
+            },
+            output: ConfigOutput::default(),
+            renderer: ConfigRenderer::default(),
+            history: ConfigHistory::default(),
+            programs: config.programs.clone(),
+        }
+    );
+
+    Ok(())
+}
+
+#[test]
+fn output_requires_path() -> anyhow::Result<()> {
+    let args = Args::parse_from(["pinix", "--pix-output=json", "nix"]);
+    assert!(Config::from_layers([args.as_toml_overrides()]).is_err());
+
+    let args = Args::parse_from([
+        "pinix",
+        "--pix-output=json",
+        "--pix-output-path=/dev/fd/3",
+        "nix",
+    ]);
+
+    let config = Config::from_layers([args.as_toml_overrides()])?;
+    assert_eq!(config.output.path, Some(PathBuf::from("/dev/fd/3")));
+    Ok(())
+}
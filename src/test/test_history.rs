@@ -0,0 +1,81 @@
+use std::time::Duration;
+
+use crate::history::BuildHistory;
+use crate::summary::BuiltDerivation;
+use crate::test::helpers::TempDir;
+
+fn built(target: &str, secs: u64, failed: bool) -> BuiltDerivation {
+    BuiltDerivation {
+        id: 0.into(),
+        target: target.to_string(),
+        machine: None,
+        duration: Duration::from_secs(secs),
+        failed,
+    }
+}
+
+#[test]
+fn estimate_from_saved_builds() -> anyhow::Result<()> {
+    let dir = TempDir::new("estimate_from_saved_builds")?;
+    let path = dir.join("build-history.json");
+    let history = BuildHistory::open(path.clone())?;
+    assert_eq!(history.estimate("/nix/store/abc-hello-2.12.drv"), None);
+
+    history.save(
+        &[
+            built("/nix/store/abc-hello-2.12.drv", 10, false),
+            built("/nix/store/abc-hello-2.12.drv", 20, false),
+            built("/nix/store/def-broken-1.0.drv", 30, true),
+        ],
+        100,
+    )?;
+
+    let history = BuildHistory::open(path.clone())?;
+    let estimate = |target| history.estimate(target).map(|d| d.as_secs());
+    assert_eq!(estimate("/nix/store/abc-hello-2.12.drv"), Some(15));
+    assert_eq!(estimate("/nix/store/xyz-hello-2.13.drv"), Some(15));
+    assert_eq!(estimate("/nix/store/def-broken-1.0.drv"), None);
+    Ok(())
+}
+
+#[test]
+fn history_size() -> anyhow::Result<()> {
+    let dir = TempDir::new("history_size")?;
+    let path = dir.join("build-history.json");
+    let mut history = BuildHistory::open(path)?;
+    let builds: Vec<_> = (0..6)
+        .map(|i| built(&format!("/nix/store/abc-pkg-1.{i}"), 1, false))
+        .collect();
+
+    // Only the latest durations are kept
+    history.record(&builds[..1], 10);
+
+    for _ in 0..10 {
+        history.record(&[built("/nix/store/abc-pkg-1.0", 9, false)], 10);
+    }
+
+    assert_eq!(
+        history.estimate("/nix/store/abc-pkg-1.0"),
+        Some(Duration::from_secs(9))
+    );
+
+    // Derivations built at the same time are kept together
+    history.record(&builds, 2);
+    assert!(history.estimate("/nix/store/abc-pkg-1.5").is_some());
+    Ok(())
+}
+
+#[test]
+fn replace_invalid_history() -> anyhow::Result<()> {
+    let dir = TempDir::new("replace_invalid_history")?;
+    let path = dir.join("build-history.json");
+    std::fs::write(&path, "{")?;
+    assert!(BuildHistory::open(path.clone()).is_err());
+
+    BuildHistory::empty(path.clone())
+        .save(&[built("/nix/store/abc-hello-2.12.drv", 10, false)], 100)?;
+
+    let history = BuildHistory::open(path.clone())?;
+    assert!(history.estimate("/nix/store/abc-hello-2.12.drv").is_some());
+    Ok(())
+}
@@ -0,0 +1,101 @@
+use indicatif::ProgressBar;
+
+use crate::action::BuildStepId;
+use crate::handlers::logs::LogsWindow;
+use crate::state::State;
+use crate::test::helpers::nix_command;
+
+/// Identifier of a build, as it would be parsed from Nix logs
+fn build(id: u64) -> BuildStepId {
+    serde_json::from_str(&id.to_string()).expect("invalid build id")
+}
+
+/// Text of each line of the window
+fn lines(window: &LogsWindow) -> Vec<String> {
+    (window.rows().into_iter())
+        .map(|(prefix, msg)| format!("{prefix} {}", console::strip_ansi_codes(&msg)))
+        .collect()
+}
+
+#[test]
+fn shared_window() -> anyhow::Result<()> {
+    let cmd = nix_command("log-window = { size = 3 }")?;
+    let mut state = State::new_captured(&cmd)?;
+    let window = LogsWindow::new(&mut state, ProgressBar::new_spinner());
+
+    window.log(build(1), "hello: 1".to_string());
+    window.log(build(2), "world: 1".to_string());
+    assert_eq!(lines(&window), ["│ ", "│ hello: 1", "└ world: 1"]);
+
+    window.select(Some((build(1), "hello".to_string())));
+    assert_eq!(lines(&window), ["│ ", "│ ", "└ hello: 1"]);
+    Ok(())
+}
+
+#[test]
+fn window_per_build() -> anyhow::Result<()> {
+    let cmd = nix_command("log-window = { mode = 'per-build', size = 6 }")?;
+    let mut state = State::new_captured(&cmd)?;
+    let window = LogsWindow::new(&mut state, ProgressBar::new_spinner());
+    assert!(lines(&window).is_empty());
+
+    window.add_build(build(1), "hello-2.12".to_string());
+    window.add_build(build(2), "world-0.3".to_string());
+
+    for i in 1..=3 {
+        window.log(build(1), format!("hello: {i}"));
+        window.log(build(2), format!("world: {i}"));
+    }
+
+    assert_eq!(
+        lines(&window),
+        [
+            "┌ hello-2.12",
+            "│ hello: 2",
+            "└ hello: 3",
+            "┌ world-0.3",
+            "│ world: 2",
+            "└ world: 3",
+        ]
+    );
+
+    // The remaining build takes all the space
+    window.remove_build(build(2));
+
+    assert_eq!(
+        lines(&window),
+        [
+            "┌ hello-2.12",
+            "│ hello: 1",
+            "│ hello: 2",
+            "│ hello: 3",
+            "│ ",
+            "└ ",
+        ]
+    );
+
+    Ok(())
+}
+
+#[test]
+fn window_per_build_overflow() -> anyhow::Result<()> {
+    let cmd = nix_command("log-window = { mode = 'per-build', size = 5 }")?;
+    let mut state = State::new_captured(&cmd)?;
+    let window = LogsWindow::new(&mut state, ProgressBar::new_spinner());
+
+    window.add_build(build(1), "hello-2.12".to_string());
+    window.add_build(build(2), "world-0.3".to_string());
+    window.log(build(2), "world: 1".to_string());
+    window.log(build(1), "hello: 1".to_string());
+
+    // Two panes don't fit, only the latest build that logged is shown
+    assert_eq!(
+        lines(&window),
+        ["┌ hello-2.12", "│ hello: 1", "│ ", "│ ", "└ "]
+    );
+
+    // The window takes at most half of the terminal
+    window.resize(80, 4);
+    assert_eq!(lines(&window), ["┌ hello-2.12", "└ hello: 1"]);
+    Ok(())
+}
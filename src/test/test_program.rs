@@ -0,0 +1,250 @@
+use crate::test::helpers::command;
+
+fn params_wrapped(cmd: &[&str]) -> anyhow::Result<Vec<String>> {
+    Ok(command(Vec::new(), cmd)?
+        .params_wrapped()
+        .map(str::to_string)
+        .collect())
+}
+
+#[test]
+fn params_prepended() -> anyhow::Result<()> {
+    assert_eq!(
+        params_wrapped(&["nix", "build", ".#hello"])?,
+        [
+            "--log-format",
+            "internal-json",
+            "--print-build-logs",
+            "build",
+            ".#hello"
+        ],
+    );
+
+    assert_eq!(
+        params_wrapped(&["/run/current-system/sw/bin/nix-build", "--print-build-logs"])?,
+        ["--log-format", "internal-json", "--print-build-logs"],
+    );
+
+    // Flags given by the user are not repeated
+    assert_eq!(
+        params_wrapped(&["nixos-rebuild", "switch", "--print-build-logs"])?,
+        [
+            "--log-format",
+            "internal-json",
+            "switch",
+            "--print-build-logs"
+        ],
+    );
+
+    Ok(())
+}
+
+#[test]
+fn params_for_subcommands() -> anyhow::Result<()> {
+    assert_eq!(
+        params_wrapped(&["nix-store", "--query", "--references", "/nix/store/x"])?,
+        ["--query", "--references", "/nix/store/x"],
+    );
+
+    assert_eq!(
+        params_wrapped(&["nix-store", "--realise", "/nix/store/x"])?,
+        ["--log-format", "internal-json", "--realise", "/nix/store/x"],
+    );
+
+    assert_eq!(
+        params_wrapped(&["nix-env", "-f", "<nixpkgs>", "-iA", "hello"])?,
+        [
+            "--log-format",
+            "internal-json",
+            "-f",
+            "<nixpkgs>",
+            "-iA",
+            "hello"
+        ],
+    );
+
+    assert_eq!(
+        params_wrapped(&["home-manager", "generations"])?,
+        ["generations"],
+    );
+
+    assert_eq!(params_wrapped(&["unknown", "build"])?, ["build"]);
+    Ok(())
+}
+
+#[test]
+fn params_appended() -> anyhow::Result<()> {
+    assert_eq!(
+        params_wrapped(&["nh", "os", "switch", "."])?,
+        [
+            "os",
+            "switch",
+            ".",
+            "--no-nom",
+            "--",
+            "--log-format",
+            "internal-json",
+            "--print-build-logs"
+        ],
+    );
+
+    assert_eq!(
+        params_wrapped(&["nh", "home", "switch", "--", "--impure"])?,
+        [
+            "home",
+            "switch",
+            "--no-nom",
+            "--",
+            "--impure",
+            "--log-format",
+            "internal-json",
+            "--print-build-logs"
+        ],
+    );
+
+    assert_eq!(
+        params_wrapped(&["nh", "search", "hello"])?,
+        ["search", "hello"]
+    );
+    Ok(())
+}
+
+#[test]
+fn repl_programs() -> anyhow::Result<()> {
+    let is_repl = |cmd| Ok::<_, anyhow::Error>(command(Vec::new(), cmd)?.is_repl());
+    assert!(is_repl(&["nix", "develop"])?);
+    assert!(is_repl(&["nix", "--impure", "shell", "nixpkgs#hello"])?);
+    assert!(is_repl(&["nix-shell", "-p", "hello"])?);
+    assert!(!is_repl(&["nix", "build", ".#shell"])?);
+    assert!(!is_repl(&["nix", "run", ".#hello", "--", "repl"])?);
+    assert!(!is_repl(&[
+        "nix",
+        "shell",
+        "nixpkgs#hello",
+        "--command",
+        "hello"
+    ])?);
+    assert!(!is_repl(&["nix-build"])?);
+
+    // Aliases of the flags that run a command instead of a session
+    assert!(!is_repl(&["nix", "develop", "-c", "make"])?);
+    assert!(!is_repl(&["nix", "shell", "nixpkgs#hello", "-c", "hello"])?);
+    assert!(!is_repl(&["nix-shell", "--command", "foo"])?);
+    assert!(!is_repl(&["nix-shell", "-p", "hello", "--run", "hello"])?);
+
+    let is_interactive = |cmd| Ok::<_, anyhow::Error>(command(Vec::new(), cmd)?.is_interactive());
+    assert!(is_interactive(&["nix", "repl", "nixpkgs"])?);
+    assert!(!is_interactive(&["nix", "develop"])?);
+    Ok(())
+}
+
+#[test]
+fn repl_prepared() -> anyhow::Result<()> {
+    assert_eq!(
+        params_wrapped(&["nix", "develop", ".#rust"])?,
+        [
+            "--log-format",
+            "internal-json",
+            "--print-build-logs",
+            "develop",
+            ".#rust",
+            "--command",
+            "true"
+        ],
+    );
+
+    assert_eq!(
+        params_wrapped(&["nix-shell", "-p", "hello"])?,
+        [
+            "--log-format",
+            "internal-json",
+            "-p",
+            "hello",
+            "--run",
+            "true"
+        ],
+    );
+
+    let cmd = command(Vec::new(), &["nix-shell", "-p", "hello"])?;
+    assert_eq!(cmd.params_unwrapped().collect::<Vec<_>>(), ["-p", "hello"]);
+    Ok(())
+}
+
+#[test]
+fn stdin_programs() -> anyhow::Result<()> {
+    let needs_stdin = |cmd| Ok::<_, anyhow::Error>(command(Vec::new(), cmd)?.needs_stdin());
+    assert!(needs_stdin(&["nix", "run", ".#hello"])?);
+    assert!(needs_stdin(&["nix-shell", "--run", "cat"])?);
+    assert!(needs_stdin(&["nixos-rebuild", "switch", "--sudo"])?);
+    assert!(needs_stdin(&["my-deploy"])?);
+    assert!(!needs_stdin(&["nix", "build", ".#hello"])?);
+    assert!(!needs_stdin(&["nixos-rebuild", "build"])?);
+
+    // Only the environment of sessions is built while pinix runs
+    assert!(!needs_stdin(&["nix", "develop"])?);
+    assert!(!needs_stdin(&["nix-shell", "-p", "hello"])?);
+    Ok(())
+}
+
+#[test]
+fn titles() -> anyhow::Result<()> {
+    let title = |cmd| Ok::<_, anyhow::Error>(command(Vec::new(), cmd)?.title());
+    assert_eq!(title(&["nix", "build", ".#hello"])?, "Running nix");
+
+    // Nothing is run when logs are piped to pinix
+    assert_eq!(title(&["", "--pix-stdin"])?, "Reading logs from stdin");
+    assert_eq!(title(&["", "--pix-replay", "nix.rec"])?, "Replaying logs");
+    Ok(())
+}
+
+#[test]
+fn keys_programs() -> anyhow::Result<()> {
+    let keys_enabled = |layers, cmd| Ok::<_, anyhow::Error>(command(layers, cmd)?.keys_enabled());
+
+    // Programs keep their stdin unless keys are enabled for them
+    assert!(!keys_enabled(Vec::new(), &["nix", "build", ".#hello"])?);
+    assert!(!keys_enabled(Vec::new(), &["nixos-rebuild", "switch"])?);
+
+    let layer = toml::toml! {
+        [programs.nix]
+        keys = ["build"]
+    };
+
+    assert!(keys_enabled(vec![layer.clone().into()], &["nix", "build"])?);
+    assert!(!keys_enabled(vec![layer.into()], &["nix", "run"])?);
+
+    let layer = toml::toml! { keys = true };
+    assert!(keys_enabled(
+        vec![layer.clone().into()],
+        &["nixos-rebuild", "switch"]
+    )?);
+
+    // Keys are never taken from a program which reads from the terminal
+    assert!(!keys_enabled(vec![layer.into()], &["nix", "run"])?);
+    Ok(())
+}
+
+#[test]
+fn programs_from_config() -> anyhow::Result<()> {
+    let layer = toml::toml! {
+        [programs.nix]
+        repl = false
+
+        [programs.my-deploy]
+        log-format = ["--log-format", "internal-json"]
+        subcommands = ["deploy"]
+    };
+
+    let cmd = command(vec![layer.clone().into()], &["nix", "develop"])?;
+    assert!(!cmd.is_repl());
+    assert_eq!(cmd.params_wrapped().count(), 4);
+
+    let cmd = command(vec![layer.into()], &["my-deploy", "deploy", "host"])?;
+
+    assert_eq!(
+        cmd.params_wrapped().collect::<Vec<_>>(),
+        ["--log-format", "internal-json", "deploy", "host"],
+    );
+
+    Ok(())
+}
@@ -0,0 +1,158 @@
+use std::io::Cursor;
+
+use crate::test::helpers::{command, TempDir};
+use crate::wrapper::record::{
+    escape_payload, unescape_payload, RecordEntry, RecordFooter, RecordHeader, RecordLine,
+    RecordReader,
+};
+use crate::wrapper::stream::OutputStream;
+
+#[test]
+fn escape_roundtrip() -> anyhow::Result<()> {
+    let payloads: &[&[u8]] = &[
+        b"hello world\n",
+        b"no trailing newline",
+        b"back\\slash\r\n",
+        b"\x1b[31;1merror:\x1b[0m colored\n",
+        b"invalid \xff\xfe utf-8\n",
+        "unicode → ok\n".as_bytes(),
+    ];
+
+    for payload in payloads {
+        let escaped = escape_payload(payload);
+        assert!(!escaped.contains('\n'));
+        assert_eq!(unescape_payload(&escaped)?, *payload);
+    }
+
+    assert!(unescape_payload("\\q").is_err());
+    assert!(unescape_payload("\\x4").is_err());
+    Ok(())
+}
+
+#[test]
+fn read_record_v1() -> anyhow::Result<()> {
+    let record = "stderr 0000029 @nix {\"action\":\"stop\",\"id\":1}\nstdout 0000030 hello\n";
+    let entries: Vec<_> = RecordReader::new(Cursor::new(record)).collect::<Result<_, _>>()?;
+
+    assert_eq!(
+        entries,
+        [
+            RecordEntry::Line(RecordLine {
+                output: OutputStream::StdErr,
+                time_ms: 29,
+                payload: b"@nix {\"action\":\"stop\",\"id\":1}\n".to_vec(),
+            }),
+            RecordEntry::Line(RecordLine {
+                output: OutputStream::StdOut,
+                time_ms: 30,
+                payload: b"hello\n".to_vec(),
+            }),
+        ]
+    );
+
+    Ok(())
+}
+
+#[test]
+fn read_record_v2() -> anyhow::Result<()> {
+    let record = concat!(
+        r#"#pinix-record {"version":2,"pinix_version":"0.3.3","command":"nix","#,
+        r#""args":["build"],"start_time":0,"term_size":{"cols":80,"rows":24},"env":{}}"#,
+        "\n",
+        "stdout 0000012 partial \\xff line\n",
+        "#pinix-footer {\"exit_code\":1,\"duration_ms\":42}\n",
+    );
+
+    let mut reader = RecordReader::new(Cursor::new(record));
+    let entries: Vec<_> = (&mut reader).collect::<Result<_, _>>()?;
+    assert_eq!(reader.version(), 2);
+    assert_eq!(entries.len(), 3);
+
+    let RecordEntry::Header(header) = &entries[0] else {
+        panic!("missing header");
+    };
+
+    assert_eq!(header.command, "nix");
+    assert_eq!(header.args, ["build"]);
+
+    assert_eq!(
+        entries[1],
+        RecordEntry::Line(RecordLine {
+            output: OutputStream::StdOut,
+            time_ms: 12,
+            payload: b"partial \xff line".to_vec(),
+        })
+    );
+
+    assert_eq!(
+        entries[2],
+        RecordEntry::Footer(RecordFooter {
+            exit_code: 1,
+            duration_ms: 42,
+        })
+    );
+
+    Ok(())
+}
+
+#[test]
+fn record_header_args() -> anyhow::Result<()> {
+    let cmd = command(Vec::new(), &["nix-shell", "shell.nix"])?;
+    assert!(cmd.params_wrapped().any(|param| param == "--run"));
+
+    // Parameters added by pinix are not part of the recorded command
+    let header = RecordHeader::new(&cmd);
+    assert_eq!(header.command, "nix-shell");
+    assert_eq!(header.args, ["shell.nix"]);
+    Ok(())
+}
+
+#[test]
+fn replay_recorded_command() -> anyhow::Result<()> {
+    let path = |path: &str| format!("{}/{path}", std::env!("CARGO_MANIFEST_DIR"));
+
+    let replay = |path: String| {
+        let mut cmd = command(Vec::new(), &["", "--pix-replay", &path])?;
+        cmd.program = cmd.take_program()?;
+        Ok::<_, anyhow::Error>(cmd)
+    };
+
+    let cmd = replay(path("src/test/data/copies.rec"))?;
+    assert_eq!(cmd.program.as_str(), "nix");
+    assert_eq!(cmd.args.ext, ["copy"]);
+
+    // Records without a header don't tell which command was run
+    let cmd = replay(path("examples/nixos-rebuild.rec"))?;
+    assert_eq!(cmd.program.as_str(), "");
+    Ok(())
+}
+
+#[test]
+fn record_spawn_error() -> anyhow::Result<()> {
+    let dir = TempDir::new("record_spawn_error")?;
+    let path = dir.join("missing.rec");
+    let path_str = path.to_string_lossy();
+    let cmd = command(
+        Vec::new(),
+        &["pinix-missing-program", "--pix-record", &path_str],
+    )?;
+
+    let runtime = tokio::runtime::Builder::new_current_thread()
+        .enable_all()
+        .build()?;
+
+    assert_eq!(runtime.block_on(cmd.exec_copycat())?, 127);
+
+    // The record is terminated even though nothing ran
+    let file = std::fs::File::open(&path)?;
+    let entries: Vec<_> =
+        RecordReader::new(std::io::BufReader::new(file)).collect::<Result<_, _>>()?;
+    assert!(matches!(entries.first(), Some(RecordEntry::Header(_))));
+
+    assert!(matches!(
+        entries.last(),
+        Some(RecordEntry::Footer(RecordFooter { exit_code: 127, .. }))
+    ));
+
+    Ok(())
+}
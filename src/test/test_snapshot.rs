@@ -0,0 +1,485 @@
+use std::fs::File;
+use std::io::BufReader;
+use std::path::PathBuf;
+
+use once_cell::sync::Lazy;
+use regex::Regex;
+
+use crate::capture::Capture;
+use crate::input::Input;
+use crate::state::State;
+use crate::test::helpers::{nix_command, TempDir};
+use crate::wrapper::record::{RecordEntry, RecordReader};
+use crate::wrapper::stream::OutputStream;
+
+/// Set this variable to write snapshots instead of checking them
+const UPDATE_VAR: &str = "PINIX_UPDATE_SNAPSHOTS";
+
+static MATCH_DURATION: Lazy<Regex> =
+    Lazy::new(|| Regex::new(r"\d+(\.\d+)?(ns|µs|ms|s)\b").expect("invalid RegEx"));
+
+static MATCH_THROUGHPUT: Lazy<Regex> =
+    Lazy::new(|| Regex::new(r"\d+(\.\d+)? [KMGT]?i?B/s").expect("invalid RegEx"));
+
+fn manifest_path(path: &str) -> PathBuf {
+    PathBuf::from(std::env!("CARGO_MANIFEST_DIR")).join(path)
+}
+
+/// Remove timing information which changes from one run to another
+fn normalize(line: &str) -> String {
+    let line = MATCH_THROUGHPUT.replace_all(line, "<rate>");
+    MATCH_DURATION.replace_all(&line, "<time>").into_owned()
+}
+
+/// Feed a record file through all handlers, as fast as possible
+fn capture_record(path: &str) -> anyhow::Result<Capture> {
+    capture_record_with_inputs(path, "", "", &[])
+}
+
+/// Feed a record file through all handlers with given configuration, keys
+/// being typed right after the first line that contains `marker`
+fn capture_record_with_inputs(
+    path: &str,
+    config: &str,
+    marker: &str,
+    inputs: &[Input],
+) -> anyhow::Result<Capture> {
+    let cmd = nix_command(config)?;
+
+    let mut state = State::new_captured(&cmd)?;
+    let file = File::open(manifest_path(path))?;
+    let mut typed = inputs.is_empty();
+
+    for entry in RecordReader::new(BufReader::new(file)) {
+        if let RecordEntry::Line(line) = entry? {
+            state.handle_line(line.output, &line.payload)?;
+
+            if !typed && String::from_utf8_lossy(&line.payload).contains(marker) {
+                typed = true;
+
+                for input in inputs {
+                    state.handle_input(*input)?;
+                }
+            }
+        }
+    }
+
+    state.finish()?;
+    let capture = state.capture.take().expect("state is not captured");
+    Ok(capture.into_inner())
+}
+
+/// Text of the snapshot: all printed lines, followed by the frames which
+/// differ from the previous one by more than their counters
+fn format_snapshot(capture: &Capture) -> String {
+    let mut result = String::from("--- printed\n");
+    let mut last_shape = None;
+
+    for line in &capture.printed {
+        result += &normalize(line);
+        result.push('\n');
+    }
+
+    for frame in &capture.frames {
+        let bars: Vec<_> = frame.bars.iter().map(|bar| normalize(bar)).collect();
+        let shape = bars.join("\n").replace(|c: char| c.is_ascii_digit(), "");
+
+        if last_shape.as_ref() == Some(&shape) {
+            continue;
+        }
+
+        result += &format!("--- frame after {} lines\n", frame.printed);
+
+        for bar in bars {
+            result += format!("  {bar}").trim_end();
+            result.push('\n');
+        }
+
+        last_shape = Some(shape);
+    }
+
+    result
+}
+
+fn check_snapshot(name: &str, capture: &Capture) -> anyhow::Result<()> {
+    let path = manifest_path(&format!("src/test/data/snapshots/{name}.txt"));
+    let actual = format_snapshot(capture);
+
+    if std::env::var_os(UPDATE_VAR).is_some() {
+        std::fs::create_dir_all(path.parent().expect("no parent directory"))?;
+        std::fs::write(&path, actual)?;
+        return Ok(());
+    }
+
+    let expected = std::fs::read_to_string(&path)?;
+
+    assert!(
+        expected == actual,
+        "snapshot {name} changed, run tests with {UPDATE_VAR}=1 to update it"
+    );
+
+    Ok(())
+}
+
+#[test]
+fn snapshot_nixos_rebuild() -> anyhow::Result<()> {
+    let capture = capture_record("examples/nixos-rebuild.rec")?;
+    check_snapshot("nixos-rebuild", &capture)?;
+
+    assert!(capture
+        .printed
+        .iter()
+        .any(|line| line.starts_with("⯈ Built 17 derivations")));
+
+    // All bars are cleared when the command exits
+    assert_eq!(capture.frames.last().map(|frame| frame.bars.len()), Some(0));
+    Ok(())
+}
+
+#[test]
+fn snapshot_nix_shell() -> anyhow::Result<()> {
+    let capture = capture_record("examples/nix-shell.rec")?;
+    check_snapshot("nix-shell", &capture)?;
+
+    let find_line = |prefix: &str| (capture.printed.iter()).any(|line| line.starts_with(prefix));
+
+    // The environment is fetched and built before the shell is started
+    assert!(find_line("⬇ Downloaded 2 derivations"));
+    assert!(find_line(
+        "✓ Built /nix/store/9fj1hyr0x2xk7wz1nwk0f1yd5s2vvvdq-my-tool-0.1"
+    ));
+    assert!(find_line("└ stripping"));
+    Ok(())
+}
+
+#[test]
+fn snapshot_copies() -> anyhow::Result<()> {
+    let capture = capture_record("src/test/data/copies.rec")?;
+    check_snapshot("copies", &capture)?;
+
+    let find_line = |prefix: &str| {
+        (capture.printed.iter())
+            .find(|line| line.starts_with(prefix))
+            .cloned()
+            .unwrap_or_default()
+    };
+
+    assert!(find_line("⬇ Downloaded 2 derivations").contains("cache.example.com"));
+    assert!(find_line("⬆ Uploaded 1 path ").contains("server1"));
+    Ok(())
+}
+
+#[test]
+fn download_summary_lines() -> anyhow::Result<()> {
+    let capture = capture_record_with_inputs(
+        "src/test/data/copies.rec",
+        "summary.download = true",
+        "",
+        &[],
+    )?;
+
+    let lines: Vec<_> = (capture.printed.iter())
+        .filter(|line| line.starts_with("⭣ Downloaded") || line.starts_with("⭡ Uploaded"))
+        .collect();
+
+    // Paths too small for a progress bar are reported as well
+    assert_eq!(lines.len(), 3, "{lines:#?}");
+    assert!(lines
+        .iter()
+        .any(|line| line.contains("hello") && line.contains("8.00 KiB")));
+    Ok(())
+}
+
+#[test]
+fn snapshot_dump_logs() -> anyhow::Result<()> {
+    let capture = capture_record_with_inputs(
+        "examples/nixos-rebuild.rec",
+        "",
+        "Validating consistency",
+        &[Input::DumpLogs, Input::NextBuild, Input::DumpLogs],
+    )?;
+
+    let pos = |prefix: &str| {
+        (capture.printed.iter())
+            .position(|line| line.contains(prefix))
+            .unwrap_or_else(|| panic!("no line with {prefix:?}"))
+    };
+
+    // Nothing is dumped before a build is selected
+    assert!(pos("Select a build") < pos("Logs of"));
+
+    // All the logs of the build so far are printed
+    assert!(capture.printed[pos("Logs of") + 1].contains("Running phase: unpackPhase"));
+    assert!(capture.printed[pos("Logs of") + 1..]
+        .iter()
+        .any(|line| line.contains("Validating consistency")));
+
+    Ok(())
+}
+
+#[test]
+fn dump_full_logs() -> anyhow::Result<()> {
+    let dir = TempDir::new("dump_full_logs")?;
+    let inputs = [Input::NextBuild, Input::DumpLogs];
+    let history = "log-history = { size = 1, failure-size = 2 }";
+
+    let dumped = |config: &str| -> anyhow::Result<Vec<String>> {
+        let capture = capture_record_with_inputs(
+            "examples/nixos-rebuild.rec",
+            &format!("{history}\n{config}"),
+            "Validating consistency",
+            &inputs,
+        )?;
+
+        let start = (capture.printed.iter())
+            .position(|line| line.contains("Logs of"))
+            .expect("logs were not dumped");
+
+        Ok(capture.printed[start + 1..].to_vec())
+    };
+
+    // Only the latest lines are kept in memory
+    let dump = dumped("")?;
+    assert!(dump[0].contains("(skipped"));
+
+    // The whole log is read back from its file
+    let dump = dumped(&format!(
+        "log-files = {{ dir = '{}' }}",
+        dir.path().display()
+    ))?;
+    assert!(dump[0].contains("Running phase: unpackPhase"));
+    Ok(())
+}
+
+#[test]
+fn save_log_files() -> anyhow::Result<()> {
+    let dir = TempDir::new("save_log_files")?;
+
+    let capture = capture_record_with_inputs(
+        "examples/nixos-rebuild.rec",
+        &format!("log-files = {{ dir = '{}' }}", dir.join("all").display()),
+        "",
+        &[],
+    )?;
+
+    let path = (dir.join("all")).join("wjrndms8j5lc2hnfkvy4ns208w0q42zy-pinix-0.1.0.log");
+    let logs = std::fs::read_to_string(&path)?;
+    assert!(logs.starts_with("Running phase: unpackPhase\n"));
+    assert!(logs.contains("Validating consistency"));
+
+    let saved = format!("ℹ Full log saved to {}", path.display());
+    assert!(capture.printed.contains(&saved));
+
+    // Builds which didn't log anything have no file
+    let nb_saved = (capture.printed.iter())
+        .filter(|line| line.contains("Full log saved"))
+        .count();
+
+    assert_eq!(std::fs::read_dir(dir.join("all"))?.count(), nb_saved);
+
+    // Logs of successful builds are removed once they are done
+    capture_record_with_inputs(
+        "examples/nixos-rebuild.rec",
+        &format!(
+            "log-files = {{ dir = '{}', keep-successful = false }}",
+            dir.join("failed").display()
+        ),
+        "",
+        &[],
+    )?;
+
+    assert_eq!(std::fs::read_dir(dir.join("failed"))?.count(), 0);
+    Ok(())
+}
+
+#[test]
+fn keep_log_file_of_late_failure() -> anyhow::Result<()> {
+    let dir = TempDir::new("keep_log_file_of_late_failure")?;
+
+    let cmd = nix_command(&format!(
+        "log-files = {{ dir = '{}', keep-successful = false }}",
+        dir.path().display()
+    ))?;
+
+    let mut state = State::new_captured(&cmd)?;
+
+    for line in [
+        r#"@nix {"action":"start","id":1,"level":3,"parent":0,"text":"","type":104}"#,
+        r#"@nix {"action":"start","fields":["/nix/store/7qy5gpaqyxmgwn1l279swisa8bsy3fg8-hello-2.12.1.drv","",1,1],"id":2,"level":3,"parent":0,"text":"","type":105}"#,
+        r#"@nix {"action":"result","fields":["make: *** [all] Error 2"],"id":2,"type":101}"#,
+        r#"@nix {"action":"stop","id":2}"#,
+        r#"@nix {"action":"msg","level":0,"msg":"error: builder for '/nix/store/7qy5gpaqyxmgwn1l279swisa8bsy3fg8-hello-2.12.1.drv' failed with exit code 2"}"#,
+        r#"@nix {"action":"stop","id":1}"#,
+    ] {
+        state.handle_line(OutputStream::StdErr, line.as_bytes())?;
+    }
+
+    state.finish()?;
+
+    // The failure was reported after the build stopped
+    let path = dir.join("7qy5gpaqyxmgwn1l279swisa8bsy3fg8-hello-2.12.1.log");
+    assert_eq!(std::fs::read_to_string(&path)?, "make: *** [all] Error 2\n");
+    Ok(())
+}
+
+#[test]
+fn report_late_failure() -> anyhow::Result<()> {
+    let cmd = nix_command("log-history = { size = 1, failure-size = 3 }")?;
+    let mut state = State::new_captured(&cmd)?;
+
+    let mut lines = vec![
+        r#"@nix {"action":"start","id":1,"level":3,"parent":0,"text":"","type":104}"#.to_string(),
+        r#"@nix {"action":"start","fields":["/nix/store/7qy5gpaqyxmgwn1l279swisa8bsy3fg8-hello-2.12.1.drv","",1,1],"id":2,"level":3,"parent":0,"text":"","type":105}"#.to_string(),
+    ];
+
+    lines.extend((1..=4).map(|i| {
+        format!(r#"@nix {{"action":"result","fields":["hello: {i}"],"id":2,"type":101}}"#)
+    }));
+
+    lines.push(r#"@nix {"action":"stop","id":2}"#.to_string());
+
+    for line in &lines {
+        state.handle_line(OutputStream::StdErr, line.as_bytes())?;
+    }
+
+    // Nothing is reported until the outcome of the build is known
+    let capture = state.capture.as_ref().expect("state is not captured");
+    assert!(capture.borrow().printed.is_empty());
+
+    for line in [
+        r#"@nix {"action":"msg","level":0,"msg":"error: builder for '/nix/store/7qy5gpaqyxmgwn1l279swisa8bsy3fg8-hello-2.12.1.drv' failed with exit code 2"}"#,
+        r#"@nix {"action":"stop","id":1}"#,
+    ] {
+        state.handle_line(OutputStream::StdErr, line.as_bytes())?;
+    }
+
+    state.finish()?;
+    let printed = state
+        .capture
+        .take()
+        .expect("state is not captured")
+        .into_inner()
+        .printed;
+
+    let pos = |prefix: &str| {
+        (printed.iter())
+            .position(|line| line.starts_with(prefix))
+            .unwrap_or_else(|| panic!("no line with {prefix:?} in {printed:#?}"))
+    };
+
+    assert!(!printed.iter().any(|line| line.starts_with("✓ Built")));
+    let failed = pos("✗ Failed to build");
+
+    assert_eq!(
+        printed[failed + 1..failed + 5],
+        [
+            "┆ ⋅⋅⋅ (skipped 1 lines)",
+            "│ hello: 2",
+            "│ hello: 3",
+            "└ hello: 4"
+        ]
+    );
+
+    Ok(())
+}
+
+#[test]
+fn events_stream() -> anyhow::Result<()> {
+    let dir = TempDir::new("events_stream")?;
+    let path = dir.join("events.json");
+
+    capture_record_with_inputs(
+        "examples/nixos-rebuild.rec",
+        &format!(
+            "output = {{ format = 'json', path = '{}' }}",
+            path.display()
+        ),
+        "",
+        &[],
+    )?;
+
+    let events: Vec<serde_json::Value> = (std::fs::read_to_string(&path)?.lines())
+        .map(serde_json::from_str)
+        .collect::<Result<_, _>>()?;
+
+    let count = |name: &str| {
+        (events.iter())
+            .filter(|event| event["event"] == name)
+            .count()
+    };
+
+    assert_eq!(count("build-started"), 17);
+    assert_eq!(count("build-finished") + count("build-failed"), 17);
+    assert_eq!(count("phase-started"), count("phase-finished"));
+
+    // Each build has a single outcome
+    let mut finished: Vec<_> = (events.iter())
+        .filter(|event| event["event"] == "build-finished" || event["event"] == "build-failed")
+        .map(|event| event["id"].as_u64())
+        .collect();
+
+    finished.sort();
+    finished.dedup();
+    assert_eq!(finished.len(), 17);
+
+    // Progress of downloads is throttled, the record holds more than a
+    // thousand updates
+    assert!((1..10).contains(&count("download-progress")));
+    assert!(count("phase") > 0);
+
+    let phase = (events.iter())
+        .find(|event| event["event"] == "phase")
+        .expect("no phase event");
+
+    assert!(events
+        .iter()
+        .any(|event| event["event"] == "build-started" && event["id"] == phase["id"]));
+    Ok(())
+}
+
+#[test]
+fn events_late_failure() -> anyhow::Result<()> {
+    let dir = TempDir::new("events_late_failure")?;
+    let path = dir.join("events.json");
+
+    let cmd = nix_command(&format!(
+        "output = {{ format = 'json', path = '{}' }}",
+        path.display()
+    ))?;
+
+    let mut state = State::new_captured(&cmd)?;
+
+    for line in [
+        r#"@nix {"action":"start","id":1,"level":3,"parent":0,"text":"","type":104}"#,
+        r#"@nix {"action":"start","fields":["/nix/store/7qy5gpaqyxmgwn1l279swisa8bsy3fg8-hello-2.12.1.drv","",1,1],"id":2,"level":3,"parent":0,"text":"","type":105}"#,
+        r#"@nix {"action":"start","fields":["/nix/store/wjrndms8j5lc2hnfkvy4ns208w0q42zy-world-0.3.drv","",1,1],"id":3,"level":3,"parent":0,"text":"","type":105}"#,
+        r#"@nix {"action":"stop","id":2}"#,
+        r#"@nix {"action":"stop","id":3}"#,
+        r#"@nix {"action":"msg","level":0,"msg":"error: builder for '/nix/store/7qy5gpaqyxmgwn1l279swisa8bsy3fg8-hello-2.12.1.drv' failed with exit code 2"}"#,
+        r#"@nix {"action":"stop","id":1}"#,
+    ] {
+        state.handle_line(OutputStream::StdErr, line.as_bytes())?;
+    }
+
+    state.finish()?;
+
+    let events: Vec<serde_json::Value> = (std::fs::read_to_string(&path)?.lines())
+        .map(serde_json::from_str)
+        .collect::<Result<_, _>>()?;
+
+    // Success is only known once the run is over
+    let outcomes: Vec<_> = (events.iter())
+        .filter(|event| event["event"] == "build-finished" || event["event"] == "build-failed")
+        .map(|event| (event["event"].as_str(), event["id"].as_u64()))
+        .collect();
+
+    assert_eq!(
+        outcomes,
+        [
+            (Some("build-failed"), Some(2)),
+            (Some("build-finished"), Some(3))
+        ]
+    );
+
+    Ok(())
+}
@@ -0,0 +1,51 @@
+use std::cell::Cell;
+use std::rc::Rc;
+
+use crate::action::Action;
+use crate::state::{Handler, HandlerResult, State};
+use crate::test::helpers::nix_command;
+use crate::wrapper::stream::OutputStream;
+
+/// A handler which can't adapt to the size of the terminal
+struct FailingResize;
+
+impl Handler for FailingResize {
+    fn on_action(&mut self, _state: &mut State, _action: &Action) -> anyhow::Result<HandlerResult> {
+        Ok(HandlerResult::Continue)
+    }
+
+    fn on_resize(&mut self, _state: &mut State) -> anyhow::Result<()> {
+        anyhow::bail!("could not resize")
+    }
+}
+
+#[test]
+fn handler_failures() -> anyhow::Result<()> {
+    let cmd = nix_command("renderer = { mode = 'plain' }\nhistory = { enabled = false }")?;
+    let mut state = State::new(&cmd)?;
+    let nb_handled = Rc::new(Cell::new(0));
+
+    state.plug(FailingResize);
+    state.plug({
+        let nb_handled = nb_handled.clone();
+
+        move |_: &mut State, _: &Action| {
+            nb_handled.set(nb_handled.get() + 1);
+            Ok(HandlerResult::Continue)
+        }
+    });
+
+    let nb_handlers = state.handlers.len();
+    let line = br#"@nix {"action":"stop","id":1}"#;
+
+    // Pretend that the terminal was resized
+    state.term_size = 0;
+    state.handle_line(OutputStream::StdErr, line)?;
+    assert_eq!(state.skipped.failed, 1);
+    assert_eq!(state.handlers.len(), nb_handlers);
+
+    state.handle_line(OutputStream::StdErr, line)?;
+    assert_eq!(nb_handled.get(), 2);
+    assert_eq!(state.skipped.failed, 1);
+    Ok(())
+}
@@ -0,0 +1,156 @@
+use crate::action::{Action, CopyDirection};
+use crate::summary::{match_failed_build, Summary};
+
+const ACTIONS: &[&str] = &[
+    r#"{"action":"start","id":1,"level":3,"parent":0,"text":"","type":104}"#,
+    r#"{"action":"start","fields":["/nix/store/7qy5gpaqyxmgwn1l279swisa8bsy3fg8-hello-2.12.1.drv","",1,1],"id":2,"level":3,"parent":0,"text":"","type":105}"#,
+    r#"{"action":"start","id":3,"level":3,"parent":0,"text":"","type":103}"#,
+    r#"{"action":"start","fields":["/nix/store/wjrndms8j5lc2hnfkvy4ns208w0q42zy-glibc-2.39","https://cache.nixos.org","local"],"id":4,"level":3,"parent":3,"text":"","type":100}"#,
+    r#"{"action":"start","fields":["https://cache.nixos.org/nar/abc.nar.xz"],"id":5,"level":4,"parent":4,"text":"","type":101}"#,
+    r#"{"action":"result","fields":[4096,8192,0,0],"id":5,"type":105}"#,
+    r#"{"action":"stop","id":5}"#,
+    r#"{"action":"stop","id":4}"#,
+    r#"{"action":"stop","id":3}"#,
+    r#"{"action":"result","fields":[0,2,1,1],"id":1,"type":105}"#,
+    r#"{"action":"msg","level":0,"msg":"error: builder for '/nix/store/7qy5gpaqyxmgwn1l279swisa8bsy3fg8-hello-2.12.1.drv' failed with exit code 2"}"#,
+    r#"{"action":"stop","id":2}"#,
+    r#"{"action":"stop","id":1}"#,
+];
+
+#[test]
+fn collect_summary() -> anyhow::Result<()> {
+    let mut summary = Summary::default();
+
+    for raw in ACTIONS {
+        summary.on_action(&Action::parse(raw)?);
+    }
+
+    assert_eq!(summary.builds.len(), 1);
+    assert!(summary.builds[0].target.ends_with("hello-2.12.1.drv"));
+    assert_eq!(summary.substituted.len(), 1);
+    assert_eq!(summary.substituted[0].size, 4096);
+    assert_eq!(
+        summary.substituted[0].substituter,
+        "https://cache.nixos.org"
+    );
+    assert_eq!(summary.total_bytes(), 4096);
+    assert_eq!(summary.failed, 1);
+
+    // The error was reported before the build stopped
+    assert!(summary.builds[0].failed);
+    assert!(summary.is_failed_step(2.into()));
+
+    // Title, two tables with their own title and totals, failed builds are
+    // not listed as slowest builds
+    assert_eq!(summary.report(5).len(), 7);
+    Ok(())
+}
+
+#[test]
+fn failed_build_attribution() -> anyhow::Result<()> {
+    let mut summary = Summary::default();
+
+    for raw in [
+        r#"{"action":"start","id":1,"level":3,"parent":0,"text":"","type":104}"#,
+        r#"{"action":"start","fields":["/nix/store/7qy5gpaqyxmgwn1l279swisa8bsy3fg8-hello-2.12.1.drv","",1,1],"id":2,"level":3,"parent":0,"text":"","type":105}"#,
+        r#"{"action":"start","fields":["/nix/store/wjrndms8j5lc2hnfkvy4ns208w0q42zy-world-0.3.drv","",1,1],"id":3,"level":3,"parent":0,"text":"","type":105}"#,
+        r#"{"action":"result","fields":[0,2,1,1],"id":1,"type":105}"#,
+        r#"{"action":"stop","id":2}"#,
+    ] {
+        summary.on_action(&Action::parse(raw)?);
+    }
+
+    // The failure counter doesn't tell which build failed
+    assert!(!summary.is_failed_step(2.into()));
+    assert!(!summary.builds[0].failed);
+
+    for raw in [
+        r#"{"action":"stop","id":3}"#,
+        r#"{"action":"msg","level":0,"msg":"error: builder for '/nix/store/7qy5gpaqyxmgwn1l279swisa8bsy3fg8-hello-2.12.1.drv' failed with exit code 2"}"#,
+    ] {
+        summary.on_action(&Action::parse(raw)?);
+    }
+
+    assert!(summary.is_failed_step(2.into()));
+    assert!(!summary.is_failed_step(3.into()));
+    assert_eq!(summary.failed_builds().count(), 1);
+    Ok(())
+}
+
+#[test]
+fn failed_build_message() {
+    let drv = "/nix/store/7qy5gpaqyxmgwn1l279swisa8bsy3fg8-hello-2.12.1.drv";
+
+    assert_eq!(
+        match_failed_build(&format!(
+            "\u{1b}[31;1merror:\u{1b}[0m builder for '\u{1b}[35;1m{drv}\u{1b}[0m' failed with exit code 2"
+        )),
+        Some(drv.to_string())
+    );
+
+    assert_eq!(
+        match_failed_build(&format!("error: Cannot build '{drv}'.")),
+        Some(drv.to_string())
+    );
+
+    assert_eq!(match_failed_build(&format!("building '{drv}'")), None);
+}
+
+#[test]
+fn build_time_per_machine() -> anyhow::Result<()> {
+    let mut summary = Summary::default();
+
+    for (id, machine) in [(1, ""), (2, "ssh://builder1"), (3, "ssh-ng://nix@builder1")] {
+        let raw = format!(
+            r#"{{"action":"start","fields":["/nix/store/7qy5gpaqyxmgwn1l279swisa8bsy3fg8-hello-2.12.{id}.drv","{machine}",1,1],"id":{id},"level":3,"parent":0,"text":"","type":105}}"#
+        );
+
+        summary.on_action(&Action::parse(&raw)?);
+        summary.on_action(&Action::parse(&format!(
+            r#"{{"action":"stop","id":{id}}}"#
+        ))?);
+    }
+
+    let machines = summary.build_time_per_machine();
+    assert_eq!(machines.len(), 2);
+    assert_eq!(machines[&Some("builder1")].1, 2);
+    assert_eq!(machines[&None].1, 1);
+
+    // Title, time per phase, slowest builds, build time per machine and totals
+    assert_eq!(summary.report(5).len(), 11);
+    Ok(())
+}
+
+#[test]
+fn copy_direction() -> anyhow::Result<()> {
+    assert_eq!(
+        CopyDirection::new("https://cache.nixos.org", "local"),
+        CopyDirection::Download
+    );
+
+    assert_eq!(
+        CopyDirection::new("ssh://builder1", "daemon"),
+        CopyDirection::Download
+    );
+
+    assert_eq!(
+        CopyDirection::new("local", "ssh-ng://deploy@server"),
+        CopyDirection::Upload
+    );
+
+    let mut summary = Summary::default();
+
+    for raw in [
+        r#"{"action":"start","fields":["/nix/store/wjrndms8j5lc2hnfkvy4ns208w0q42zy-glibc-2.39","local","ssh://server"],"id":1,"level":3,"parent":0,"text":"","type":100}"#,
+        r#"{"action":"result","fields":[2048,4096,0,0],"id":1,"type":105}"#,
+        r#"{"action":"stop","id":1}"#,
+    ] {
+        summary.on_action(&Action::parse(raw)?);
+    }
+
+    assert!(summary.substituted.is_empty());
+    assert_eq!(summary.uploaded.len(), 1);
+    assert_eq!(summary.uploaded[0].size, 2048);
+    assert_eq!(summary.uploaded[0].substituter, "ssh://server");
+    Ok(())
+}
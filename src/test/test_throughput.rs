@@ -0,0 +1,31 @@
+use std::time::{Duration, Instant};
+
+use crate::util::throughput::Throughput;
+
+#[test]
+fn windowed_rate() {
+    let start = Instant::now();
+    let at = |secs| start + Duration::from_secs(secs);
+    let mut throughput = Throughput::new(Duration::from_secs(5));
+
+    // No rate is known before samples cover enough time
+    throughput.push(at(0), 0);
+    assert_eq!(throughput.rate(start), None);
+    assert_eq!(throughput.eta(start, 100), None);
+
+    for secs in 1..=10 {
+        throughput.push(at(secs), 100 * secs);
+    }
+
+    assert_eq!(throughput.rate(at(10)), Some(100.));
+    assert_eq!(throughput.eta(at(10), 1500), Some(Duration::from_secs(5)));
+
+    // Older samples are forgotten
+    throughput.push(at(11), 2000);
+    assert_eq!(throughput.rate(at(11)), Some(280.));
+    assert_eq!(throughput.peak(), Some(280.));
+
+    // The rate drops when the counter stalls, but not the peak
+    assert_eq!(throughput.rate(at(16)), Some(140.));
+    assert_eq!(throughput.peak(), Some(280.));
+}
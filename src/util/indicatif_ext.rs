@@ -7,12 +7,19 @@ use tokio::time::MissedTickBehavior;
 pub trait ProgressBarExt {
     fn get_bar(&self) -> &ProgressBar;
 
+    /// Tick the bar periodically, which is only possible from within a
+    /// runtime. Bars that are only captured don't need it.
     fn spawn_steady_tick(&self, interval: Duration) {
+        let Ok(runtime) = tokio::runtime::Handle::try_current() else {
+            return;
+        };
+
         let pb_weak = self.get_bar().downgrade();
-        let mut interval = tokio::time::interval(interval);
-        interval.set_missed_tick_behavior(MissedTickBehavior::Delay);
 
-        tokio::spawn(async move {
+        runtime.spawn(async move {
+            let mut interval = tokio::time::interval(interval);
+            interval.set_missed_tick_behavior(MissedTickBehavior::Delay);
+
             loop {
                 interval.tick().await;
                 let Some(pb) = pb_weak.upgrade() else { break };
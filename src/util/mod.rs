@@ -1,2 +1,3 @@
 pub mod indicatif_ext;
+pub mod throughput;
 pub mod toml_ext;
@@ -0,0 +1,66 @@
+use std::collections::VecDeque;
+use std::time::{Duration, Instant};
+
+/// Samples must cover this much time before a rate is computed, which avoids
+/// displaying a meaningless rate when a transfer starts
+const MIN_SPAN: Duration = Duration::from_secs(1);
+
+/// Rate at which a counter grows, computed over a sliding window of time
+#[derive(Debug)]
+pub struct Throughput {
+    window: Duration,
+    samples: VecDeque<(Instant, u64)>,
+    peak: f64,
+}
+
+impl Throughput {
+    pub fn new(window: Duration) -> Self {
+        Self {
+            window,
+            samples: VecDeque::new(),
+            peak: 0.,
+        }
+    }
+
+    /// Register the current value of the counter
+    pub fn push(&mut self, now: Instant, total: u64) {
+        self.samples.push_back((now, total));
+
+        // Keep a sample older than the window so that the window is covered
+        while self.samples.len() > 2 && now - self.samples[1].0 >= self.window {
+            self.samples.pop_front();
+        }
+
+        if let Some(rate) = self.rate(now) {
+            self.peak = self.peak.max(rate);
+        }
+    }
+
+    /// Units per second over the window, which decreases if the counter
+    /// stops increasing
+    pub fn rate(&self, now: Instant) -> Option<f64> {
+        let (start, first) = *self.samples.front()?;
+        let (_, last) = *self.samples.back()?;
+        let span = now.checked_duration_since(start)?;
+
+        if span < MIN_SPAN {
+            return None;
+        }
+
+        Some(last.saturating_sub(first) as f64 / span.as_secs_f64())
+    }
+
+    /// Time needed for the counter to reach `target` at current rate
+    pub fn eta(&self, now: Instant, target: u64) -> Option<Duration> {
+        let (_, last) = *self.samples.back()?;
+        let rate = self.rate(now).filter(|rate| *rate > 0.)?;
+        Some(Duration::from_secs_f64(
+            target.saturating_sub(last) as f64 / rate,
+        ))
+    }
+
+    /// Highest rate that was observed
+    pub fn peak(&self) -> Option<f64> {
+        (self.peak > 0.).then_some(self.peak)
+    }
+}
@@ -1,7 +1,8 @@
 use std::path::PathBuf;
 
 use crate::util::toml_ext::TomlBuilder;
-use crate::wrapper::command::WrappedProgram;
+use crate::wrapper::config::{LogWindowMode, OutputFormat, RendererMode};
+use crate::wrapper::program::WrappedProgram;
 
 #[derive(Debug, clap::Parser)]
 #[command(
@@ -21,36 +22,121 @@ pub struct Args {
     )]
     pub command: Option<WrappedProgram>,
 
-    #[arg(long = "pix-debug", help = "Display a debug bar")]
+    #[arg(
+        long = "pix-debug",
+        help = "Display a debug bar",
+        num_args = 0..=1,
+        require_equals = true,
+        default_missing_value = "true"
+    )]
     pub debug: Option<bool>,
 
+    #[arg(
+        long = "pix-pty",
+        help = "Run the command in a pseudo-terminal",
+        num_args = 0..=1,
+        require_equals = true,
+        default_missing_value = "true"
+    )]
+    pub pty: Option<bool>,
+
+    #[arg(
+        long = "pix-keys",
+        help = "Control the display from the keyboard",
+        num_args = 0..=1,
+        require_equals = true,
+        default_missing_value = "true"
+    )]
+    pub keys: Option<bool>,
+
     #[arg(
         long = "pix-summary-download",
-        help = "Display a summary line when a download is finished"
+        help = "Display a summary line when a download is finished",
+        num_args = 0..=1,
+        require_equals = true,
+        default_missing_value = "true"
     )]
     pub summary_download: Option<bool>,
 
+    #[arg(
+        long = "pix-summary-report",
+        help = "Display a report of the whole run when the command exits",
+        num_args = 0..=1,
+        require_equals = true,
+        default_missing_value = "true"
+    )]
+    pub summary_report: Option<bool>,
+
     #[arg(
         long = "pix-log-window-size",
         help = "Size of the window displaying build logs"
     )]
     pub log_window_size: Option<u32>,
 
+    #[arg(
+        long = "pix-log-window-mode",
+        help = "Interleave logs of all builds or give each build its own pane"
+    )]
+    pub log_window_mode: Option<LogWindowMode>,
+
     #[arg(
         long = "pix-log-history",
         help = "Restrict the size of the log history of each build"
     )]
-    pub log_history: Option<Option<usize>>,
+    pub log_history: Option<u32>,
 
     #[arg(
         long = "pix-log-history-failure",
         help = "Restrict the size of the log history of each build in case of failure"
     )]
-    pub log_history_failure: Option<Option<usize>>,
+    pub log_history_failure: Option<u32>,
+
+    #[arg(
+        long = "pix-log-dir",
+        help = "Write full logs of each build to a directory"
+    )]
+    pub log_dir: Option<PathBuf>,
+
+    #[arg(
+        long = "pix-renderer",
+        help = "Display progress bars or plain lines of log"
+    )]
+    pub renderer: Option<RendererMode>,
+
+    #[arg(
+        long = "pix-output",
+        help = "Write a machine-readable stream of events"
+    )]
+    pub output: Option<OutputFormat>,
+
+    #[arg(
+        long = "pix-output-path",
+        help = "Write the stream of events to a file, required by --pix-output"
+    )]
+    pub output_path: Option<PathBuf>,
 
     #[arg(long = "pix-record", help = "Save timestamped logs to a file")]
     pub record: Option<PathBuf>,
 
+    #[arg(
+        long = "pix-stdin",
+        help = "Display logs read from stdin instead of running a command"
+    )]
+    pub stdin: bool,
+
+    #[arg(
+        long = "pix-replay",
+        help = "Display logs from a record file instead of running a command"
+    )]
+    pub replay: Option<PathBuf>,
+
+    #[arg(
+        long = "pix-replay-speed",
+        help = "Speedup replay by given factor",
+        default_value = "1.0"
+    )]
+    pub replay_speed: f64,
+
     #[clap(help = "Arguments forwared to actual Nix command")]
     pub ext: Vec<String>,
 }
@@ -59,8 +145,30 @@ impl Args {
     pub fn as_toml_overrides(&self) -> toml::Value {
         TomlBuilder::default()
             .with_opt(["debug"], self.debug)
+            .with_opt(["pty"], self.pty)
+            .with_opt(["keys"], self.keys)
             .with_opt(["summary", "download"], self.summary_download)
+            .with_opt(["summary", "report"], self.summary_report)
+            .with_opt(["log-history", "size"], self.log_history)
+            .with_opt(["log-history", "failure-size"], self.log_history_failure)
+            .with_opt(
+                ["log-files", "dir"],
+                (self.log_dir.as_ref()).map(|path| path.to_string_lossy().into_owned()),
+            )
             .with_opt(["log-window", "size"], self.log_window_size)
+            .with_opt(
+                ["log-window", "mode"],
+                self.log_window_mode.map(|mode| mode.as_str()),
+            )
+            .with_opt(
+                ["renderer", "mode"],
+                self.renderer.map(|mode| mode.as_str()),
+            )
+            .with_opt(["output", "format"], self.output.map(|fmt| fmt.as_str()))
+            .with_opt(
+                ["output", "path"],
+                (self.output_path.as_ref()).map(|path| path.to_string_lossy().into_owned()),
+            )
             .build()
     }
 }
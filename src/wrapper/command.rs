@@ -1,5 +1,6 @@
 use std::iter;
-use std::path::PathBuf;
+use std::os::unix::process::{CommandExt, ExitStatusExt};
+use std::path::Path;
 use std::process::Stdio;
 
 use anyhow::Context;
@@ -7,100 +8,34 @@ use clap::{CommandFactory, Parser};
 use console::style;
 use tokio::process;
 
-use crate::state::monitor_logs;
-
-use super::stream::MergedStreams;
-
-#[derive(Debug, clap::Parser)]
-#[command(
-    allow_hyphen_values = true,
-    disable_help_flag = true,
-    disable_version_flag = true,
-    trailing_var_arg = true
-)]
-/// Wrap a Nix command to display rich logs while it is running.
-pub struct Args {
-    #[clap(long = "pix-help", help = "Display this help message")]
-    pub help: bool,
-
-    #[arg(
-        long = "pix-command",
-        help = "Specify the nix command that must be run"
-    )]
-    pub command: Option<WrappedProgram>,
-
-    #[arg(long = "pix-debug", help = "Display a debug bar")]
-    pub debug: bool,
-
-    #[arg(
-        long = "pix-show-downloads",
-        help = "Display a summary line when a download is finished"
-    )]
-    pub show_downloads: bool,
-
-    #[arg(
-        long = "pix-log-window",
-        help = "Size of the window displaying build logs",
-        default_value = "5"
-    )]
-    pub log_window_len: usize,
-
-    #[arg(
-        long = "pix-log-history",
-        help = "Restrict the size of the log history of each build [default: unrestricted]"
-    )]
-    pub log_history_len: Option<usize>,
-
-    #[arg(long = "pix-record", help = "Save timestamped logs to a file")]
-    pub record: Option<PathBuf>,
-
-    #[clap(help = "Arguments forwared to actual Nix command")]
-    pub ext: Vec<String>,
-}
+use crate::state::{monitor_logs, Renderer};
 
-#[derive(Clone, Debug)]
-pub enum WrappedProgram {
-    Nix,
-    NixCollectGarbage,
-    NixOsRebuild,
-    NixShell,
-    Unknown(String),
-}
+use super::args::Args;
+use super::config::Config;
+use super::program::{ParamsPosition, ProgramSpec, WrappedProgram};
+use super::record::{RecordHeader, RecordSource, Recorder};
+use super::signal::SignalForwarder;
+use super::stream::{LogSource, MergedStreams, Pty};
 
-impl WrappedProgram {
-    pub fn as_str(&self) -> &str {
-        match self {
-            WrappedProgram::Nix => "nix",
-            WrappedProgram::NixCollectGarbage => "nix-collect-garbage",
-            WrappedProgram::NixOsRebuild => "nixos-rebuild",
-            WrappedProgram::NixShell => "nix-shell",
-            WrappedProgram::Unknown(path) => path.as_str(),
-        }
-    }
-}
+/// Exit status of pinix when it fails by itself, which is distinct from the
+/// statuses used by Nix and by shells
+pub const EXIT_INTERNAL_ERROR: i32 = 125;
 
-impl From<String> for WrappedProgram {
-    fn from(value: String) -> Self {
-        match value.as_str() {
-            "nix" => Self::Nix,
-            "nix-collect-garbage" => Self::NixCollectGarbage,
-            "nixos-rebuild" => Self::NixOsRebuild,
-            "nix-shell" => Self::NixShell,
-            _ => Self::Unknown(value),
-        }
-    }
-}
+/// Exit status of pinix when it is called with invalid parameters, as for
+/// other command line tools
+const EXIT_USAGE: i32 = 2;
 
-impl std::fmt::Display for WrappedProgram {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        f.write_str(self.as_str())
-    }
-}
+/// Exit status of shells when the command is found but can't be executed
+const EXIT_NOT_EXECUTABLE: i32 = 126;
+
+/// Exit status of shells when the command is not found
+const EXIT_NOT_FOUND: i32 = 127;
 
 #[derive(Debug)]
 pub struct NixCommand {
     pub program: WrappedProgram,
     pub args: Args,
+    pub config: Config,
 }
 
 impl NixCommand {
@@ -109,36 +44,132 @@ impl NixCommand {
     }
 
     pub fn params_wrapped(&self) -> impl Iterator<Item = &'_ str> + '_ {
-        self.extra_params()
-            .map(|s| s as _)
-            .chain(self.args.ext.iter().map(String::as_str))
+        let extra = self.extra_params();
+        let mut user: Vec<_> = self.params_unwrapped().collect();
+
+        if let Some(spec) = self.program_spec().filter(|_| self.is_repl()) {
+            user.extend(spec.prepare.iter().map(String::as_str));
+        }
+
+        let params = match self.program_spec().map(|spec| spec.position) {
+            None | Some(ParamsPosition::Start) => [extra, user].concat(),
+            Some(ParamsPosition::End) => {
+                // Parameters that follow a separator must stay after it
+                let (user_head, user_tail) = split_separator(&user);
+                let (extra_head, extra_tail) = split_separator(&extra);
+
+                let separator = (user_tail.is_some() || extra_tail.is_some()).then_some("--");
+
+                (user_head.iter().chain(extra_head))
+                    .copied()
+                    .chain(separator)
+                    .chain(user_tail.into_iter().flatten().copied())
+                    .chain(extra_tail.into_iter().flatten().copied())
+                    .collect()
+            }
+        };
+
+        params.into_iter()
+    }
+
+    /// Definition of the wrapped program from the configuration, if it is
+    /// known
+    pub fn program_spec(&self) -> Option<&ProgramSpec> {
+        self.config.programs.get(self.program.name())
     }
 
+    /// Check if the command opens an interactive session once its
+    /// environment is built, which is not the case if the user already asked
+    /// for it to run something else
     pub fn is_repl(&self) -> bool {
-        matches!(
-            (&self.program, self.args.ext.first().map(String::as_str)),
-            (WrappedProgram::NixShell, _)
-                | (WrappedProgram::Nix, Some("repl" | "develop" | "shell"))
-        )
-    }
-
-    fn extra_params(&self) -> impl Iterator<Item = &'static str> + '_ {
-        let required: &[(&str, &[_])] = match (&self.program, self.args.ext.as_slice()) {
-            (WrappedProgram::Nix | WrappedProgram::NixOsRebuild, &[..]) => &[
-                ("--print-build-logs", &[]),
-                ("--log-format", &["internal-json"]),
-            ],
-            (WrappedProgram::NixCollectGarbage | WrappedProgram::NixShell, &[..]) => {
-                &[("--log-format", &["internal-json"])]
-            }
-            (WrappedProgram::Unknown(_), &[..]) => &[],
+        self.program_spec().is_some_and(|spec| {
+            spec.repl.matches(&self.args.ext)
+                && (self.args.ext.iter())
+                    .take_while(|arg| *arg != "--")
+                    .all(|arg| !spec.run_flags.contains(arg))
+        })
+    }
+
+    /// Check if the command is only interactive, in which case pinix has
+    /// nothing to display
+    pub fn is_interactive(&self) -> bool {
+        self.program_spec()
+            .is_some_and(|spec| spec.interactive.matches(&self.args.ext))
+    }
+
+    /// Check if the command may read from the terminal, in which case it
+    /// keeps its stdin. Unknown programs are assumed to do so.
+    pub fn needs_stdin(&self) -> bool {
+        self.program_spec()
+            .is_none_or(|spec| spec.stdin.matches(&self.args.ext) && !self.is_repl())
+    }
+
+    /// Check if keys typed while the command runs may be read by pinix. When
+    /// a command is run, this must be enabled for it as it keeps its stdin
+    /// otherwise.
+    pub fn keys_enabled(&self) -> bool {
+        if self.args.replay.is_some() {
+            return self.config.keys.unwrap_or(true);
+        }
+
+        // Stdin carries logs when they are piped to pinix
+        if self.args.stdin || self.needs_stdin() {
+            return false;
+        }
+
+        self.config.keys.unwrap_or_else(|| {
+            self.program_spec()
+                .is_some_and(|spec| spec.keys.matches(&self.args.ext))
+        })
+    }
+
+    /// Title displayed above the bars, which tells where logs come from
+    pub fn title(&self) -> String {
+        if !self.program.is_empty() {
+            format!("Running {}", self.program)
+        } else if self.args.stdin {
+            "Reading logs from stdin".to_string()
+        } else {
+            "Replaying logs".to_string()
+        }
+    }
+
+    /// Check if keys typed while the command runs are handled by pinix
+    pub fn use_keyboard(&self) -> bool {
+        self.keys_enabled() && Renderer::from_mode(self.config.renderer.mode) == Renderer::Tty
+    }
+
+    fn extra_params(&self) -> Vec<&str> {
+        let Some(spec) = self.program_spec() else {
+            return Vec::new();
         };
 
-        required
-            .iter()
-            .filter(move |(flag, _)| self.args.ext.iter().all(|arg| arg != flag))
-            .flat_map(move |(flag, vals)| iter::once(flag).chain(*vals))
-            .copied()
+        if !spec.subcommands.matches(&self.args.ext) {
+            return Vec::new();
+        }
+
+        let params: Vec<_> = spec.params().collect();
+        let mut result = Vec::new();
+
+        // Each flag is followed by its values, it is skipped if it was given
+        // by the user
+        for group in params.chunk_by(|_, next| !next.starts_with('-')) {
+            let flag = group[0];
+
+            if flag == "--" || self.args.ext.iter().all(|arg| arg != flag) {
+                result.extend(group);
+            }
+        }
+
+        result
+    }
+}
+
+/// Split parameters around the first `--` separator, if there is one
+fn split_separator<'a, 'p>(params: &'a [&'p str]) -> (&'a [&'p str], Option<&'a [&'p str]>) {
+    match params.iter().position(|param| *param == "--") {
+        Some(pos) => (&params[..pos], Some(&params[pos + 1..])),
+        None => (params, None),
     }
 }
 
@@ -146,7 +177,7 @@ impl NixCommand {
     pub fn from_program_and_args(
         program: WrappedProgram,
         args: impl Iterator<Item = String>,
-    ) -> Self {
+    ) -> anyhow::Result<Self> {
         let args = Args::parse_from(iter::once(program.to_string()).chain(args));
 
         if args.help {
@@ -157,16 +188,40 @@ impl NixCommand {
             std::process::exit(0);
         }
 
-        Self { program, args }
+        let config = Config::load(args.as_toml_overrides())?;
+
+        Ok(Self {
+            program,
+            args,
+            config,
+        })
     }
 
-    pub fn from_args(args: impl Iterator<Item = String>) -> Self {
-        let mut cmd = Self::from_program_and_args(WrappedProgram::Unknown(String::new()), args);
+    pub fn from_args(args: impl Iterator<Item = String>) -> anyhow::Result<Self> {
+        let mut cmd = Self::from_program_and_args(WrappedProgram::default(), args)?;
+        cmd.program = cmd.take_program()?;
+        Ok(cmd)
+    }
 
-        let program: WrappedProgram = {
-            if let Some(program) = cmd.args.command.clone() {
+    /// Find which program the parameters given to pinix run, the program is
+    /// removed from the parameters if it is the first of them
+    pub fn take_program(&mut self) -> anyhow::Result<WrappedProgram> {
+        let program = {
+            if let Some(program) = self.args.command.clone() {
                 program
-            } else if cmd.args.ext.is_empty() {
+            } else if let (true, Some(path)) = (self.args.ext.is_empty(), &self.args.replay) {
+                // Replays are displayed as the recorded command
+                match RecordHeader::read(path)? {
+                    Some(header) => {
+                        self.args.ext = header.args;
+                        header.command.into()
+                    }
+                    None => WrappedProgram::default(),
+                }
+            } else if self.args.ext.is_empty() && self.args.stdin {
+                // Logs are read from elsewhere, nothing is run
+                WrappedProgram::default()
+            } else if self.args.ext.is_empty() {
                 eprintln!(
                     "{}: No program to execute",
                     style("error").bright().red().bold()
@@ -176,45 +231,201 @@ impl NixCommand {
                     .print_help()
                     .expect("failed to display help message");
 
-                std::process::exit(1);
+                std::process::exit(EXIT_USAGE);
             } else {
-                cmd.args.ext.remove(0).into()
+                self.args.ext.remove(0).into()
             }
         };
 
-        cmd.program = program;
-        cmd
+        Ok(program)
     }
 
-    pub async fn exec_copycat(&self) -> anyhow::Result<()> {
-        let mut child = process::Command::new(self.program.as_str())
-            .args(self.params_wrapped())
-            .stdin(Stdio::null())
-            .stderr(Stdio::piped())
-            .stdout(Stdio::piped())
-            .spawn()
-            .context("failed to spawn command")?;
+    /// Run the command while displaying its logs, then return the exit status
+    /// that pinix should exit with
+    pub async fn exec_copycat(&self) -> anyhow::Result<i32> {
+        if self.is_interactive() {
+            return Ok(self.exec_unwrapped());
+        }
 
-        let logs_stream =
-            MergedStreams::new(&mut child).context("could not pipe command output")?;
+        // Anything that may fail is set up before the child is spawned, so
+        // that it is always waited for once it runs
+        let signal_forwarder = SignalForwarder::listen()?;
 
-        monitor_logs(self, logs_stream).await?;
-        let exit_code = child.wait().await.context("child command failed")?;
+        let mut recorder = {
+            if let Some(path) = &self.args.record {
+                Some(Recorder::create(path, &RecordHeader::new(self)).await?)
+            } else {
+                None
+            }
+        };
 
-        if !exit_code.success() {
-            std::process::exit(exit_code.code().context("unknown exit code")?);
+        let result = self
+            .run_monitored(signal_forwarder, recorder.as_mut())
+            .await;
+
+        // The record is always terminated, with the status pinix exits with
+        if let Some(recorder) = recorder {
+            let code = *result.as_ref().unwrap_or(&EXIT_INTERNAL_ERROR);
+            let finished = recorder.finish(code).await;
+
+            // Errors of the command are reported before errors of the record
+            if result.is_ok() {
+                finished?;
+            }
         }
 
-        if self.is_repl() {
-            process::Command::new(self.program.as_str())
-                .args(self.params_unwrapped())
-                .spawn()
-                .context("failed to spawn repl command")?
-                .wait()
-                .await
-                .context("replcommand failed")?;
+        let code = result?;
+
+        if code == 0 && self.is_repl() {
+            // Dependencies of the session were realised by the first run
+            return Ok(self.exec_unwrapped());
+        }
+
+        Ok(code)
+    }
+
+    /// Spawn the command and display its logs until it exits, then return its
+    /// exit status
+    async fn run_monitored(
+        &self,
+        signal_forwarder: SignalForwarder,
+        recorder: Option<&mut Recorder>,
+    ) -> anyhow::Result<i32> {
+        // Keys typed in the terminal are read by pinix instead
+        let stdin = {
+            if self.use_keyboard() {
+                Stdio::null()
+            } else {
+                Stdio::inherit()
+            }
+        };
+
+        let mut command = process::Command::new(self.program.as_str());
+        command.args(self.params_wrapped()).stdin(stdin);
+
+        let ptys = {
+            if self.config.pty {
+                let [stdout, stderr] = [Pty::open()?, Pty::open()?];
+                command.stdout(stdout.stdio()?).stderr(stderr.stdio()?);
+                Some((stdout, stderr))
+            } else {
+                command.stdout(Stdio::piped()).stderr(Stdio::piped());
+                None
+            }
+        };
+
+        let mut child = match command.spawn() {
+            Ok(child) => child,
+            Err(err) => return Ok(self.report_spawn_error(err)),
+        };
+
+        // Release handles to the child's side of the terminals
+        drop(command);
+
+        let signal_forwarder = signal_forwarder.forward_to(&child);
+
+        let monitored = async {
+            let mut logs_source = {
+                if let Some((stdout, stderr)) = ptys {
+                    LogSource::Process(MergedStreams::from_readers(
+                        stdout.into_reader(),
+                        stderr.into_reader(),
+                    ))
+                } else {
+                    LogSource::Process(
+                        MergedStreams::new(&mut child).context("could not pipe command output")?,
+                    )
+                }
+            };
+
+            let monitored = monitor_logs(self, &mut logs_source, recorder).await;
+
+            if monitored.is_err() {
+                // The command is left running, its output is drained so that
+                // it doesn't block on writing it
+                eprintln!(
+                    "{}: lost track of logs, waiting for {} to exit",
+                    style("warning").yellow().bold(),
+                    self.program,
+                );
+
+                while let Ok(Some(_)) = logs_source.next_line().await {}
+            }
+
+            monitored
+        }
+        .await;
+
+        let exit_status = signal_forwarder.wait(&mut child).await?;
+        monitored?;
+
+        exit_status
+            .code()
+            .or_else(|| exit_status.signal().map(|signal| 128 + signal))
+            .context("unknown exit code")
+    }
+
+    /// Replace pinix with the command run as is, so that it gets control of
+    /// the terminal. This only returns if the command could not be started.
+    fn exec_unwrapped(&self) -> i32 {
+        let err = std::process::Command::new(self.program.as_str())
+            .args(self.params_unwrapped())
+            .exec();
+
+        self.report_spawn_error(err)
+    }
+
+    /// Print why the command could not be started, and return the matching
+    /// exit status
+    fn report_spawn_error(&self, err: std::io::Error) -> i32 {
+        eprintln!(
+            "{}: failed to run {}: {err}",
+            style("error").bright().red().bold(),
+            self.program,
+        );
+
+        match err.kind() {
+            std::io::ErrorKind::NotFound => EXIT_NOT_FOUND,
+            std::io::ErrorKind::PermissionDenied => EXIT_NOT_EXECUTABLE,
+            _ if err.raw_os_error() == Some(libc::ENOEXEC) => EXIT_NOT_EXECUTABLE,
+            _ => EXIT_INTERNAL_ERROR,
+        }
+    }
+
+    /// Display the logs from a record file, as if the recorded command was
+    /// running
+    pub async fn exec_replay(&self, path: &Path) -> anyhow::Result<i32> {
+        let mut record = RecordSource::open(path, self.args.replay_speed)?;
+        monitor_logs(self, &mut LogSource::Record(&mut record), None).await?;
+        Ok(record.exit_code().unwrap_or(0))
+    }
+
+    /// Display the logs piped to pinix, until stdin is closed
+    pub async fn exec_stdin(&self) -> anyhow::Result<i32> {
+        let mut recorder = {
+            if let Some(path) = &self.args.record {
+                Some(Recorder::create(path, &RecordHeader::new(self)).await?)
+            } else {
+                None
+            }
+        };
+
+        let result = monitor_logs(self, &mut LogSource::stdin(), recorder.as_mut()).await;
+
+        if let Some(recorder) = recorder {
+            let code = if result.is_ok() {
+                0
+            } else {
+                EXIT_INTERNAL_ERROR
+            };
+            let finished = recorder.finish(code).await;
+
+            if result.is_ok() {
+                finished?;
+            }
         }
 
-        Ok(())
+        result?;
+        Ok(0)
     }
 }
@@ -1,11 +1,30 @@
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+use anyhow::Context;
 use serde::Deserialize;
 
+use crate::util::toml_ext::TomlExt;
+use crate::wrapper::program::{ProgramSpec, DEFAULT_PROGRAMS};
+
+/// Name of the per-project configuration file, looked up from the current
+/// directory and its ancestors.
+const PROJECT_CONFIG_NAME: &str = ".pinix.toml";
+
 #[derive(Debug, Deserialize, PartialEq)]
 #[serde(deny_unknown_fields, rename_all = "kebab-case")]
 pub struct Config {
     #[serde(default = "df_false")]
     pub debug: bool,
 
+    #[serde(default = "df_false")]
+    pub pty: bool,
+
+    /// Listen to the keyboard while the command runs, which is otherwise
+    /// only done for programs that enable it
+    #[serde(default)]
+    pub keys: Option<bool>,
+
     #[serde(default)]
     pub summary: ConfigSummary,
 
@@ -14,6 +33,102 @@ pub struct Config {
 
     #[serde(default)]
     pub log_window: ConfigLogWindow,
+
+    #[serde(default)]
+    pub log_files: ConfigLogFiles,
+
+    #[serde(default)]
+    pub output: ConfigOutput,
+
+    #[serde(default)]
+    pub renderer: ConfigRenderer,
+
+    #[serde(default)]
+    pub history: ConfigHistory,
+
+    /// Programs that pinix knows how to wrap, indexed by name
+    #[serde(default)]
+    pub programs: BTreeMap<String, ProgramSpec>,
+}
+
+impl Config {
+    /// Load user and project configurations from disk, then apply overrides
+    /// on top of them.
+    pub fn load(overrides: toml::Value) -> anyhow::Result<Self> {
+        let mut layers = Vec::new();
+
+        for path in [user_config_path(), project_config_path()]
+            .into_iter()
+            .flatten()
+        {
+            if let Some(layer) = read_config_file(&path)? {
+                layers.push(layer);
+            }
+        }
+
+        layers.push(overrides);
+        Self::from_layers(layers)
+    }
+
+    /// Build the configuration by merging each layer on top of the known
+    /// programs, the last one having the highest priority.
+    pub fn from_layers(layers: impl IntoIterator<Item = toml::Value>) -> anyhow::Result<Self> {
+        let programs: toml::Table = DEFAULT_PROGRAMS
+            .parse()
+            .context("invalid definition of known programs")?;
+
+        let config: Self = layers
+            .into_iter()
+            .fold(programs.into(), |acc: toml::Value, layer| {
+                acc.with_overrides(layer)
+            })
+            .try_into()
+            .context("invalid configuration")?;
+
+        // Events would get mixed with the output of the command or the
+        // interface if they were written to a standard stream
+        anyhow::ensure!(
+            config.output.format == OutputFormat::None || config.output.path.is_some(),
+            "invalid configuration: a path is required to write events to"
+        );
+
+        Ok(config)
+    }
+}
+
+/// Path to the user's configuration, following XDG conventions
+fn user_config_path() -> Option<PathBuf> {
+    let config_home = std::env::var_os("XDG_CONFIG_HOME")
+        .filter(|path| !path.is_empty())
+        .map(PathBuf::from)
+        .or_else(|| Some(PathBuf::from(std::env::var_os("HOME")?).join(".config")))?;
+
+    Some(config_home.join("pinix").join("config.toml"))
+}
+
+/// Path to the closest project configuration in the current directory or its
+/// ancestors
+fn project_config_path() -> Option<PathBuf> {
+    let current_dir = std::env::current_dir().ok()?;
+
+    current_dir
+        .ancestors()
+        .map(|dir| dir.join(PROJECT_CONFIG_NAME))
+        .find(|path| path.is_file())
+}
+
+fn read_config_file(path: &Path) -> anyhow::Result<Option<toml::Value>> {
+    let raw = match std::fs::read_to_string(path) {
+        Ok(raw) => raw,
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+        Err(err) => return Err(err).with_context(|| format!("could not read {}", path.display())),
+    };
+
+    let table: toml::Table = raw
+        .parse()
+        .with_context(|| format!("invalid TOML in {}", path.display()))?;
+
+    Ok(Some(table.into()))
 }
 
 // Summary
@@ -23,6 +138,12 @@ pub struct Config {
 pub struct ConfigSummary {
     #[serde(default = "df_false")]
     pub download: bool,
+
+    #[serde(default = "df_false")]
+    pub report: bool,
+
+    #[serde(default = "df_summary_report_size")]
+    pub report_size: usize,
 }
 
 impl Default for ConfigSummary {
@@ -31,6 +152,10 @@ impl Default for ConfigSummary {
     }
 }
 
+fn df_summary_report_size() -> usize {
+    5
+}
+
 // Log History
 
 #[derive(Debug, Deserialize, PartialEq)]
@@ -53,6 +178,26 @@ fn df_log_history_size() -> usize {
     1000
 }
 
+// Log Files
+
+#[derive(Debug, PartialEq, Eq, Deserialize)]
+#[serde(deny_unknown_fields, rename_all = "kebab-case")]
+pub struct ConfigLogFiles {
+    /// If specified, full logs of each build are written to this directory
+    #[serde(default)]
+    pub dir: Option<PathBuf>,
+
+    /// Keep logs of successful builds, logs of failed builds are always kept
+    #[serde(default = "df_true")]
+    pub keep_successful: bool,
+}
+
+impl Default for ConfigLogFiles {
+    fn default() -> Self {
+        toml::from_str("").unwrap()
+    }
+}
+
 // Log Window
 
 #[derive(Debug, PartialEq, Eq, Deserialize)]
@@ -60,6 +205,14 @@ fn df_log_history_size() -> usize {
 pub struct ConfigLogWindow {
     #[serde(default = "df_log_window_size")]
     pub size: usize,
+
+    #[serde(default)]
+    pub mode: LogWindowMode,
+
+    /// If specified, only logs of these build phases are displayed in the
+    /// window
+    #[serde(default)]
+    pub phases: Option<Vec<String>>,
 }
 
 impl Default for ConfigLogWindow {
@@ -72,30 +225,139 @@ fn df_log_window_size() -> usize {
     10
 }
 
+/// How logs of builds running in parallel share the window
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize, clap::ValueEnum)]
+#[serde(rename_all = "kebab-case")]
+pub enum LogWindowMode {
+    /// Latest lines of all builds, interleaved
+    #[default]
+    Shared,
+    /// A labelled pane for each running build
+    PerBuild,
+}
+
+impl LogWindowMode {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            LogWindowMode::Shared => "shared",
+            LogWindowMode::PerBuild => "per-build",
+        }
+    }
+}
+
+// Output
+
+#[derive(Debug, PartialEq, Eq, Deserialize)]
+#[serde(deny_unknown_fields, rename_all = "kebab-case")]
+pub struct ConfigOutput {
+    #[serde(default)]
+    pub format: OutputFormat,
+
+    /// Where events are written, required unless the format is `none`
+    #[serde(default)]
+    pub path: Option<PathBuf>,
+}
+
+impl Default for ConfigOutput {
+    fn default() -> Self {
+        toml::from_str("").unwrap()
+    }
+}
+
+/// Machine-readable stream of events written alongside regular display
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize, clap::ValueEnum)]
+#[serde(rename_all = "kebab-case")]
+pub enum OutputFormat {
+    #[default]
+    None,
+    Json,
+}
+
+impl OutputFormat {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            OutputFormat::None => "none",
+            OutputFormat::Json => "json",
+        }
+    }
+}
+
+// Renderer
+
+#[derive(Debug, PartialEq, Eq, Deserialize)]
+#[serde(deny_unknown_fields, rename_all = "kebab-case")]
+pub struct ConfigRenderer {
+    #[serde(default)]
+    pub mode: RendererMode,
+
+    /// Interval in seconds between two progress reports of the plain renderer
+    #[serde(default = "df_renderer_progress_interval")]
+    pub progress_interval: u64,
+}
+
+impl Default for ConfigRenderer {
+    fn default() -> Self {
+        toml::from_str("").unwrap()
+    }
+}
+
+fn df_renderer_progress_interval() -> u64 {
+    10
+}
+
+/// How the progress is displayed
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize, clap::ValueEnum)]
+#[serde(rename_all = "kebab-case")]
+pub enum RendererMode {
+    /// Use progress bars if stderr is a terminal, plain lines otherwise
+    #[default]
+    Auto,
+    /// Always use progress bars
+    Tty,
+    /// Always print plain lines, which is suited for CI logs
+    Plain,
+}
+
+impl RendererMode {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            RendererMode::Auto => "auto",
+            RendererMode::Tty => "tty",
+            RendererMode::Plain => "plain",
+        }
+    }
+}
+
+// History
+
+#[derive(Debug, PartialEq, Eq, Deserialize)]
+#[serde(deny_unknown_fields, rename_all = "kebab-case")]
+pub struct ConfigHistory {
+    /// Keep track of build durations to estimate remaining time
+    #[serde(default = "df_true")]
+    pub enabled: bool,
+
+    /// Maximum number of derivations kept in the history
+    #[serde(default = "df_history_size")]
+    pub size: usize,
+}
+
+impl Default for ConfigHistory {
+    fn default() -> Self {
+        toml::from_str("").unwrap()
+    }
+}
+
+fn df_history_size() -> usize {
+    2000
+}
+
 // Common Defaults
 
 fn df_false() -> bool {
     false
 }
 
-// /// TOML doesn't support null values, so options are implemented through "false"
-// fn deserialize_opt_bool<'de, D, T: Deserialize<'de>>(deserializer: D) -> Result<Option<T>, D::Error>
-// where
-//     D: de::Deserializer<'de>,
-// {
-//     #[derive(Deserialize)]
-//     #[serde(untagged)]
-//     enum Either<T> {
-//         Value(T),
-//         Boolean(bool),
-//     }
-//
-//     match Either::<T>::deserialize(deserializer)? {
-//         Either::Value(val) => Ok(Some(val)),
-//         Either::Boolean(false) => Ok(None),
-//         Either::Boolean(true) => Err(de::Error::invalid_value(
-//             de::Unexpected::Bool(true),
-//             &"a value or false",
-//         )),
-//     }
-// }
+fn df_true() -> bool {
+    true
+}
@@ -1,4 +1,7 @@
 pub mod args;
 pub mod command;
 pub mod config;
+pub mod program;
+pub mod record;
+pub mod signal;
 pub mod stream;
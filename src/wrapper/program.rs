@@ -1,33 +1,39 @@
-#[derive(Clone, Debug)]
-pub enum WrappedProgram {
-    Nix,
-    NixCollectGarbage,
-    NixOsRebuild,
-    NixShell,
-    Unknown(String),
+use std::path::Path;
+
+use serde::Deserialize;
+
+/// Configuration layer describing the programs known by pinix
+pub const DEFAULT_PROGRAMS: &str = include_str!("programs.toml");
+
+/// A program run by pinix, which is described by the `programs` table of the
+/// configuration if it is known.
+#[derive(Clone, Debug, Default)]
+pub struct WrappedProgram {
+    path: String,
 }
 
 impl WrappedProgram {
     pub fn as_str(&self) -> &str {
-        match self {
-            WrappedProgram::Nix => "nix",
-            WrappedProgram::NixCollectGarbage => "nix-collect-garbage",
-            WrappedProgram::NixOsRebuild => "nixos-rebuild",
-            WrappedProgram::NixShell => "nix-shell",
-            WrappedProgram::Unknown(path) => path.as_str(),
-        }
+        &self.path
+    }
+
+    /// Check if no program is run, such as when logs are piped to pinix
+    pub fn is_empty(&self) -> bool {
+        self.path.is_empty()
+    }
+
+    /// Name used to find the program in the configuration
+    pub fn name(&self) -> &str {
+        Path::new(&self.path)
+            .file_name()
+            .and_then(|name| name.to_str())
+            .unwrap_or(&self.path)
     }
 }
 
 impl From<String> for WrappedProgram {
-    fn from(value: String) -> Self {
-        match value.as_str() {
-            "nix" => Self::Nix,
-            "nix-collect-garbage" => Self::NixCollectGarbage,
-            "nixos-rebuild" => Self::NixOsRebuild,
-            "nix-shell" => Self::NixShell,
-            _ => Self::Unknown(value),
-        }
+    fn from(path: String) -> Self {
+        Self { path }
     }
 }
 
@@ -36,3 +42,114 @@ impl std::fmt::Display for WrappedProgram {
         f.write_str(self.as_str())
     }
 }
+
+/// How pinix must call a program to get rich logs out of it
+#[derive(Clone, Debug, Deserialize, Eq, PartialEq)]
+#[serde(deny_unknown_fields, rename_all = "kebab-case")]
+pub struct ProgramSpec {
+    /// Parameters which make the program write its logs as internal JSON
+    #[serde(default)]
+    pub log_format: Vec<String>,
+
+    /// Parameters which make the program forward logs of builds
+    #[serde(default)]
+    pub print_build_logs: Vec<String>,
+
+    /// Parameters are only added when one of these sub-commands is run
+    #[serde(default = "Subcommands::all")]
+    pub subcommands: Subcommands,
+
+    /// Sub-commands which open an interactive session once the build is done
+    #[serde(default = "Subcommands::none")]
+    pub repl: Subcommands,
+
+    /// Parameters which make REPL sub-commands exit as soon as their
+    /// environment is ready, the interactive session is then started again
+    /// without these
+    #[serde(default)]
+    pub prepare: Vec<String>,
+
+    /// Flags with which the user asks a REPL sub-command to run something
+    /// instead of opening an interactive session
+    #[serde(default)]
+    pub run_flags: Vec<String>,
+
+    /// Sub-commands which are only interactive, they are run without pinix
+    #[serde(default = "Subcommands::none")]
+    pub interactive: Subcommands,
+
+    /// Sub-commands which may read from the terminal while pinix runs them,
+    /// keys are then left to the program
+    #[serde(default = "Subcommands::none")]
+    pub stdin: Subcommands,
+
+    /// Sub-commands during which keys typed in the terminal control pinix,
+    /// the program is otherwise left with the terminal as its stdin
+    #[serde(default = "Subcommands::none")]
+    pub keys: Subcommands,
+
+    #[serde(default)]
+    pub position: ParamsPosition,
+}
+
+impl ProgramSpec {
+    /// All the parameters that must be added to the command line
+    pub fn params(&self) -> impl Iterator<Item = &str> + '_ {
+        (self.log_format.iter())
+            .chain(&self.print_build_logs)
+            .map(String::as_str)
+    }
+}
+
+/// Set of sub-commands, either given as a list or as a boolean to match all
+/// or none of them.
+///
+/// Names are compared with the first positional argument while flags such as
+/// `--realise` are looked for in all arguments before the `--` separator.
+#[derive(Clone, Debug, Deserialize, Eq, PartialEq)]
+#[serde(untagged)]
+pub enum Subcommands {
+    All(bool),
+    List(Vec<String>),
+}
+
+impl Subcommands {
+    fn all() -> Self {
+        Self::All(true)
+    }
+
+    fn none() -> Self {
+        Self::All(false)
+    }
+
+    pub fn matches(&self, args: &[String]) -> bool {
+        let list = match self {
+            Self::All(all) => return *all,
+            Self::List(list) => list,
+        };
+
+        let args = args.iter().take_while(|arg| *arg != "--");
+        let subcommand = args.clone().find(|arg| !arg.starts_with('-'));
+
+        list.iter().any(|expected| {
+            if expected.starts_with('-') {
+                args.clone().any(|arg| arg == expected)
+            } else {
+                subcommand == Some(expected)
+            }
+        })
+    }
+}
+
+/// Where extra parameters are inserted in the command line
+#[derive(Clone, Copy, Debug, Default, Deserialize, Eq, PartialEq)]
+#[serde(rename_all = "kebab-case")]
+pub enum ParamsPosition {
+    /// Before parameters of the user, which fits programs accepting flags
+    /// anywhere
+    #[default]
+    Start,
+    /// After parameters of the user, parameters following a `--` separator
+    /// are moved after the separator of the user if there is one
+    End,
+}
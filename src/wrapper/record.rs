@@ -0,0 +1,358 @@
+use std::collections::BTreeMap;
+use std::fmt::Write as _;
+use std::io::BufRead;
+use std::path::Path;
+use std::time::{Duration, Instant, SystemTime};
+
+use anyhow::Context;
+use serde::{Deserialize, Serialize};
+use tokio::io::{AsyncWriteExt, BufWriter};
+
+use super::command::NixCommand;
+use super::stream::OutputStream;
+
+/// Version of the record format written by pinix.
+///
+/// The first version holds a line per line of output, formatted as
+/// `<stream> <ms> <line>`. The second version starts with a header line and
+/// ends with a footer line, both holding JSON metadata. Its payloads are
+/// escaped so that they keep their exact bytes, including invalid UTF-8
+/// sequences and the trailing newline when there is one.
+pub const RECORD_VERSION: u32 = 2;
+
+const HEADER_PREFIX: &str = "#pinix-record ";
+const FOOTER_PREFIX: &str = "#pinix-footer ";
+
+/// Environment variables that affect how the command renders its output
+const ENV_HINTS: &[&str] = &["TERM", "COLORTERM", "NO_COLOR", "LANG", "LC_ALL"];
+
+#[derive(Debug, Deserialize, Eq, PartialEq, Serialize)]
+pub struct TermSize {
+    pub cols: u16,
+    pub rows: u16,
+}
+
+#[derive(Debug, Deserialize, Eq, PartialEq, Serialize)]
+pub struct RecordHeader {
+    pub version: u32,
+    pub pinix_version: String,
+    pub command: String,
+    pub args: Vec<String>,
+    /// Seconds since UNIX epoch
+    pub start_time: u64,
+    pub term_size: Option<TermSize>,
+    pub env: BTreeMap<String, String>,
+}
+
+impl RecordHeader {
+    pub fn new(cmd: &NixCommand) -> Self {
+        let start_time = SystemTime::now()
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .map(|elapsed| elapsed.as_secs())
+            .unwrap_or_default();
+
+        let term_size = console::Term::stderr()
+            .size_checked()
+            .map(|(rows, cols)| TermSize { cols, rows });
+
+        let env = ENV_HINTS
+            .iter()
+            .filter_map(|key| Some((key.to_string(), std::env::var(key).ok()?)))
+            .collect();
+
+        Self {
+            version: RECORD_VERSION,
+            pinix_version: env!("CARGO_PKG_VERSION").to_string(),
+            command: cmd.program.to_string(),
+            args: cmd.params_unwrapped().map(str::to_string).collect(),
+            start_time,
+            term_size,
+            env,
+        }
+    }
+
+    /// Read the header of a record file, records of the first version of the
+    /// format have none
+    pub fn read(path: &Path) -> anyhow::Result<Option<Self>> {
+        let file = std::fs::File::open(path).context("could not open record file")?;
+
+        match RecordReader::new(std::io::BufReader::new(file)).next() {
+            Some(Ok(RecordEntry::Header(header))) => Ok(Some(header)),
+            Some(Err(err)) => Err(err),
+            _ => Ok(None),
+        }
+    }
+}
+
+#[derive(Debug, Deserialize, Eq, PartialEq, Serialize)]
+pub struct RecordFooter {
+    pub exit_code: i32,
+    pub duration_ms: u128,
+}
+
+/// A line of output from the recorded command
+#[derive(Debug, Eq, PartialEq)]
+pub struct RecordLine {
+    pub output: OutputStream,
+    /// Milliseconds since the command started
+    pub time_ms: u64,
+    /// Exact bytes written by the command, including the trailing newline
+    pub payload: Vec<u8>,
+}
+
+#[derive(Debug, Eq, PartialEq)]
+pub enum RecordEntry {
+    Header(RecordHeader),
+    Line(RecordLine),
+    Footer(RecordFooter),
+}
+
+/// Escape a payload so that it fits on a single line of valid UTF-8
+pub fn escape_payload(payload: &[u8]) -> String {
+    let mut result = String::with_capacity(payload.len());
+
+    for chunk in payload.utf8_chunks() {
+        for c in chunk.valid().chars() {
+            match c {
+                '\\' => result.push_str("\\\\"),
+                '\n' => result.push_str("\\n"),
+                '\r' => result.push_str("\\r"),
+                '\t' => result.push('\t'),
+                c if c.is_ascii_control() => write!(&mut result, "\\x{:02x}", c as u8).unwrap(),
+                c => result.push(c),
+            }
+        }
+
+        for byte in chunk.invalid() {
+            write!(&mut result, "\\x{byte:02x}").unwrap();
+        }
+    }
+
+    result
+}
+
+pub fn unescape_payload(escaped: &str) -> anyhow::Result<Vec<u8>> {
+    let mut result = Vec::with_capacity(escaped.len());
+    let mut bytes = escaped.bytes();
+
+    while let Some(byte) = bytes.next() {
+        if byte != b'\\' {
+            result.push(byte);
+            continue;
+        }
+
+        match bytes.next() {
+            Some(b'\\') => result.push(b'\\'),
+            Some(b'n') => result.push(b'\n'),
+            Some(b'r') => result.push(b'\r'),
+            Some(b'x') => {
+                let hex = [bytes.next(), bytes.next()]
+                    .map(|digit| digit.and_then(|d| (d as char).to_digit(16)));
+
+                let [Some(high), Some(low)] = hex else {
+                    anyhow::bail!("invalid byte escape sequence");
+                };
+
+                result.push((high * 16 + low) as u8);
+            }
+            Some(other) => anyhow::bail!("unknown escape sequence `\\{}`", other as char),
+            None => anyhow::bail!("unterminated escape sequence"),
+        }
+    }
+
+    Ok(result)
+}
+
+/// Read entries of a record file, in any version of the format
+pub struct RecordReader<R: BufRead> {
+    reader: R,
+    version: u32,
+    buffer: String,
+}
+
+impl<R: BufRead> RecordReader<R> {
+    pub fn new(reader: R) -> Self {
+        Self {
+            reader,
+            version: 1,
+            buffer: String::new(),
+        }
+    }
+
+    /// Version of the format, which is only known once the header is read
+    pub fn version(&self) -> u32 {
+        self.version
+    }
+
+    fn parse_line(&mut self, line: &str) -> anyhow::Result<RecordEntry> {
+        if let Some(header) = line.strip_prefix(HEADER_PREFIX) {
+            let header: RecordHeader =
+                serde_json::from_str(header).context("invalid record header")?;
+
+            if header.version > RECORD_VERSION {
+                anyhow::bail!("unsupported record version {}", header.version);
+            }
+
+            self.version = header.version;
+            return Ok(RecordEntry::Header(header));
+        }
+
+        if let Some(footer) = line.strip_prefix(FOOTER_PREFIX) {
+            let footer = serde_json::from_str(footer).context("invalid record footer")?;
+            return Ok(RecordEntry::Footer(footer));
+        }
+
+        let mut cols = line.splitn(3, ' ');
+        let output = cols.next().context("missing output column")?.parse()?;
+
+        let time_ms = (cols.next().context("missing delay column")?)
+            .parse()
+            .context("invalid delay")?;
+
+        let raw_payload = cols.next().unwrap_or("");
+
+        let payload = {
+            if self.version >= 2 {
+                unescape_payload(raw_payload)?
+            } else {
+                // The first version strips newlines from the end of lines
+                format!("{raw_payload}\n").into_bytes()
+            }
+        };
+
+        Ok(RecordEntry::Line(RecordLine {
+            output,
+            time_ms,
+            payload,
+        }))
+    }
+}
+
+impl<R: BufRead> Iterator for RecordReader<R> {
+    type Item = anyhow::Result<RecordEntry>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.buffer.clear();
+
+        match self.reader.read_line(&mut self.buffer) {
+            Ok(0) => None,
+            Ok(_) => {
+                let line = std::mem::take(&mut self.buffer);
+                let line = line.strip_suffix('\n').unwrap_or(&line);
+                Some(self.parse_line(line))
+            }
+            Err(err) => Some(Err(err).context("could not read line from record")),
+        }
+    }
+}
+
+/// Write the output of a command to a record file, using the latest version
+/// of the format
+pub struct Recorder {
+    file: BufWriter<tokio::fs::File>,
+    start: Instant,
+}
+
+impl Recorder {
+    pub async fn create(path: &Path, header: &RecordHeader) -> anyhow::Result<Self> {
+        let file = tokio::fs::File::create(path)
+            .await
+            .context("could not open record file")?;
+
+        let mut recorder = Self {
+            file: BufWriter::new(file),
+            start: Instant::now(),
+        };
+
+        let header = serde_json::to_string(header).context("could not serialize header")?;
+        recorder
+            .write(&format!("{HEADER_PREFIX}{header}\n"))
+            .await?;
+        Ok(recorder)
+    }
+
+    async fn write(&mut self, line: &str) -> anyhow::Result<()> {
+        self.file
+            .write_all(line.as_bytes())
+            .await
+            .context("error writing record file")
+    }
+
+    pub async fn write_line(&mut self, output: OutputStream, payload: &[u8]) -> anyhow::Result<()> {
+        let line = format!(
+            "{} {:07} {}\n",
+            output.as_str(),
+            self.start.elapsed().as_millis(),
+            escape_payload(payload),
+        );
+
+        self.write(&line).await
+    }
+
+    pub async fn finish(mut self, exit_code: i32) -> anyhow::Result<()> {
+        let footer = RecordFooter {
+            exit_code,
+            duration_ms: self.start.elapsed().as_millis(),
+        };
+
+        let footer = serde_json::to_string(&footer).context("could not serialize footer")?;
+        self.write(&format!("{FOOTER_PREFIX}{footer}\n")).await?;
+        self.file.flush().await.context("error saving record file")
+    }
+}
+
+/// Read back lines of a record file, at the pace they were recorded
+pub struct RecordSource {
+    entries: RecordReader<std::io::BufReader<std::fs::File>>,
+    speed: f64,
+    start: Instant,
+    buffer: Vec<u8>,
+    /// Line waiting for its time to be displayed, which is kept if waiting is
+    /// cancelled
+    pending: Option<RecordLine>,
+    exit_code: Option<i32>,
+}
+
+impl RecordSource {
+    pub fn open(path: &Path, speed: f64) -> anyhow::Result<Self> {
+        anyhow::ensure!(speed > 0., "replay speed must be positive");
+        let file = std::fs::File::open(path).context("could not open record file")?;
+
+        Ok(Self {
+            entries: RecordReader::new(std::io::BufReader::new(file)),
+            speed,
+            start: Instant::now(),
+            buffer: Vec::new(),
+            pending: None,
+            exit_code: None,
+        })
+    }
+
+    /// Exit status of the recorded command, which is only known once all
+    /// lines are read from a record that has a footer
+    pub fn exit_code(&self) -> Option<i32> {
+        self.exit_code
+    }
+
+    pub async fn next_line(&mut self) -> anyhow::Result<Option<(OutputStream, &[u8])>> {
+        while self.pending.is_none() {
+            match self.entries.next().transpose()? {
+                None => return Ok(None),
+                Some(RecordEntry::Header(_)) => {}
+                Some(RecordEntry::Footer(footer)) => {
+                    self.exit_code = Some(footer.exit_code);
+                    return Ok(None);
+                }
+                Some(RecordEntry::Line(line)) => self.pending = Some(line),
+            }
+        }
+
+        let time_ms = self.pending.as_ref().map_or(0, |line| line.time_ms);
+        let time = Duration::from_millis(time_ms).div_f64(self.speed);
+        tokio::time::sleep(time.saturating_sub(self.start.elapsed())).await;
+
+        let line = self.pending.take().expect("no pending line");
+        self.buffer = line.payload;
+        Ok(Some((line.output, &self.buffer)))
+    }
+}
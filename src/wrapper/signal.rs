@@ -0,0 +1,143 @@
+use std::fs::File;
+use std::os::fd::AsRawFd;
+use std::process::ExitStatus;
+
+use anyhow::Context;
+use tokio::process::Child;
+use tokio::signal::unix::{signal, Signal, SignalKind};
+use tokio::task::JoinHandle;
+
+/// Signals that are forwarded to the wrapped command
+const FORWARDED_SIGNALS: [SignalKind; 3] = [
+    SignalKind::interrupt(),
+    SignalKind::terminate(),
+    SignalKind::hangup(),
+];
+
+/// Signals which the terminal sends to its whole foreground process group
+const TERMINAL_SIGNALS: [SignalKind; 1] = [SignalKind::interrupt()];
+
+/// Forward termination signals received by pinix to the wrapped command, so
+/// that pinix keeps on draining its output until it exits by itself.
+///
+/// The child shares the process group of pinix, which is required for it to
+/// read from the terminal when it prompts the user. Signals are thus sent to
+/// the child itself rather than to its process group, which would loop back to
+/// pinix. Signals typed on the terminal already reach the child when this
+/// group is in the foreground, they are not sent again as Nix would take a
+/// second interrupt as a request to abort right away.
+pub struct SignalForwarder {
+    streams: Vec<(SignalKind, Signal)>,
+}
+
+impl SignalForwarder {
+    /// Start listening for signals. This is done before the child is spawned,
+    /// so that failing to do so can't leave it behind.
+    pub fn listen() -> anyhow::Result<Self> {
+        let streams = FORWARDED_SIGNALS
+            .into_iter()
+            .map(|kind| Ok((kind, signal(kind)?)))
+            .collect::<std::io::Result<Vec<_>>>()
+            .context("could not listen for signals")?;
+
+        Ok(Self { streams })
+    }
+
+    /// Forward signals received since listening to the child
+    pub fn forward_to(self, child: &Child) -> Forwarding {
+        let Some(pid) = child.id().and_then(|pid| libc::pid_t::try_from(pid).ok()) else {
+            return Forwarding { task: None };
+        };
+
+        let mut streams = self.streams;
+
+        let task = tokio::spawn(async move {
+            loop {
+                let received =
+                    futures::future::select_all(streams.iter_mut().map(|(kind, stream)| {
+                        Box::pin(async move { stream.recv().await.map(|_| *kind) })
+                    }))
+                    .await
+                    .0;
+
+                let Some(kind) = received else { break };
+
+                if TERMINAL_SIGNALS.contains(&kind) && in_foreground() {
+                    continue;
+                }
+
+                // Safety: kill has no memory safety requirements. The PID is
+                // not recycled while forwarding as the child is only reaped
+                // once this task stopped, see `Forwarding::wait`.
+                unsafe { libc::kill(pid, kind.as_raw_value()) };
+            }
+        });
+
+        Forwarding { task: Some(task) }
+    }
+}
+
+/// Signals being forwarded to a running child
+pub struct Forwarding {
+    task: Option<JoinHandle<()>>,
+}
+
+impl Forwarding {
+    /// Wait for the child to exit, then stop forwarding signals before it is
+    /// reaped, so that they can't reach another process which would reuse
+    /// its PID.
+    pub async fn wait(mut self, child: &mut Child) -> anyhow::Result<ExitStatus> {
+        if let Some(task) = self.task.take() {
+            if let Some(pid) = child.id() {
+                tokio::task::spawn_blocking(move || wait_exited(pid))
+                    .await
+                    .context("could not wait for child command")??;
+            }
+
+            task.abort();
+            task.await.ok();
+        }
+
+        child.wait().await.context("child command failed")
+    }
+}
+
+/// Check if the process group of pinix, which the child shares, is the one
+/// the controlling terminal sends its signals to
+fn in_foreground() -> bool {
+    let Ok(tty) = File::open("/dev/tty") else {
+        return false;
+    };
+
+    // Safety: these calls have no memory safety requirements, and the file
+    // descriptor is kept open by `tty` until they return
+    unsafe { libc::tcgetpgrp(tty.as_raw_fd()) == libc::getpgrp() }
+}
+
+/// Block until a process exited, without reaping it
+fn wait_exited(pid: u32) -> anyhow::Result<()> {
+    loop {
+        // Safety: siginfo_t is a plain C struct which is filled by waitid
+        let res = unsafe {
+            let mut info = std::mem::zeroed();
+            libc::waitid(libc::P_PID, pid, &mut info, libc::WEXITED | libc::WNOWAIT)
+        };
+
+        match res {
+            0 => return Ok(()),
+            _ if std::io::Error::last_os_error().kind() == std::io::ErrorKind::Interrupted => {}
+            _ => {
+                return Err(std::io::Error::last_os_error())
+                    .context("could not wait for child command")
+            }
+        }
+    }
+}
+
+impl Drop for Forwarding {
+    fn drop(&mut self) {
+        if let Some(task) = &self.task {
+            task.abort();
+        }
+    }
+}
@@ -1,10 +1,18 @@
+use std::io;
+use std::os::fd::{AsRawFd, FromRawFd, OwnedFd};
+use std::pin::Pin;
+use std::process::Stdio;
 use std::str::FromStr;
+use std::task::{Context as TaskContext, Poll};
 
 use anyhow::Context;
 use futures::FutureExt;
-use tokio::io::{AsyncBufReadExt, AsyncRead, BufReader};
-use tokio::process::{Child, ChildStderr, ChildStdout};
+use tokio::io::{AsyncBufReadExt, AsyncRead, BufReader, ReadBuf};
+use tokio::process::Child;
 
+use super::record::RecordSource;
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
 pub enum OutputStream {
     StdOut,
     StdErr,
@@ -64,12 +72,114 @@ impl<R: AsyncRead + Unpin> BorrowLines<R> {
     }
 }
 
+/// A pseudo-terminal that can be used as an output of the child command, which
+/// makes it behave as if it was writing directly to a terminal.
+pub struct Pty {
+    master: OwnedFd,
+    slave: OwnedFd,
+}
+
+impl Pty {
+    /// Open a new pseudo-terminal with the same size as current terminal
+    pub fn open() -> anyhow::Result<Self> {
+        let (rows, cols) = console::Term::stderr().size();
+        let mut master = -1;
+        let mut slave = -1;
+
+        let size = libc::winsize {
+            ws_row: rows,
+            ws_col: cols,
+            ws_xpixel: 0,
+            ws_ypixel: 0,
+        };
+
+        // Safety: pointers are valid for the duration of the call and file
+        // descriptors are owned after it succeeds.
+        let (master, slave) = unsafe {
+            if libc::openpty(
+                &mut master,
+                &mut slave,
+                std::ptr::null_mut(),
+                std::ptr::null(),
+                &size,
+            ) != 0
+            {
+                return Err(io::Error::last_os_error()).context("could not open pseudo-terminal");
+            }
+
+            (OwnedFd::from_raw_fd(master), OwnedFd::from_raw_fd(slave))
+        };
+
+        let pty = Self { master, slave };
+        pty.set_raw()?;
+        Ok(pty)
+    }
+
+    /// Disable output processing, which would otherwise translate line feeds
+    /// into CRLF sequences.
+    fn set_raw(&self) -> anyhow::Result<()> {
+        let fd = self.slave.as_raw_fd();
+
+        // Safety: termios is a plain C struct that is fully initialized by
+        // tcgetattr before being used.
+        unsafe {
+            let mut termios = std::mem::zeroed();
+
+            if libc::tcgetattr(fd, &mut termios) != 0 {
+                return Err(io::Error::last_os_error()).context("could not read pty attributes");
+            }
+
+            libc::cfmakeraw(&mut termios);
+
+            if libc::tcsetattr(fd, libc::TCSANOW, &termios) != 0 {
+                return Err(io::Error::last_os_error()).context("could not set pty attributes");
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Get a handle to the terminal that can be passed to the child command
+    pub fn stdio(&self) -> anyhow::Result<Stdio> {
+        let slave = self.slave.try_clone().context("could not duplicate pty")?;
+        Ok(slave.into())
+    }
+
+    /// Read from the terminal. This must be called after the child was spawned
+    /// as it closes our end of the terminal, which ensures that the reader
+    /// reaches EOF when the child exits.
+    pub fn into_reader(self) -> PtyReader {
+        PtyReader(tokio::fs::File::from_std(self.master.into()))
+    }
+}
+
+/// Reading side of a pseudo-terminal
+pub struct PtyReader(tokio::fs::File);
+
+impl AsyncRead for PtyReader {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut TaskContext<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        match Pin::new(&mut self.0).poll_read(cx, buf) {
+            // Linux reports an IO error instead of EOF once all processes
+            // closed the other end of the terminal
+            Poll::Ready(Err(err)) if err.raw_os_error() == Some(libc::EIO) => Poll::Ready(Ok(())),
+            res => res,
+        }
+    }
+}
+
+type BoxedReader<'c> = Box<dyn AsyncRead + Unpin + 'c>;
+
 pub struct MergedStreams<'c> {
-    stdout: BorrowLines<&'c mut ChildStdout>,
-    stderr: BorrowLines<&'c mut ChildStderr>,
+    stdout: BorrowLines<BoxedReader<'c>>,
+    stderr: BorrowLines<BoxedReader<'c>>,
 }
 
 impl<'c> MergedStreams<'c> {
+    /// Read output of a child command spawned with piped stdout and stderr
     pub fn new(child: &'c mut Child) -> anyhow::Result<Self> {
         let stdout = child
             .stdout
@@ -81,10 +191,17 @@ impl<'c> MergedStreams<'c> {
             .as_mut()
             .context("could not read child command output")?;
 
-        Ok(Self {
-            stdout: BorrowLines::new(stdout),
-            stderr: BorrowLines::new(stderr),
-        })
+        Ok(Self::from_readers(stdout, stderr))
+    }
+
+    pub fn from_readers(
+        stdout: impl AsyncRead + Unpin + 'c,
+        stderr: impl AsyncRead + Unpin + 'c,
+    ) -> Self {
+        Self {
+            stdout: BorrowLines::new(Box::new(stdout)),
+            stderr: BorrowLines::new(Box::new(stderr)),
+        }
     }
 
     pub async fn next_line(&mut self) -> anyhow::Result<Option<(OutputStream, &[u8])>> {
@@ -103,3 +220,33 @@ impl<'c> MergedStreams<'c> {
         }
     }
 }
+
+/// Lines of output to monitor, which come from a running command or are read
+/// back from a record file
+pub enum LogSource<'c> {
+    Process(MergedStreams<'c>),
+    Record(&'c mut RecordSource),
+    Stdin(StdinLines),
+}
+
+impl LogSource<'_> {
+    /// Read lines piped to pinix, such as the merged output of a Nix command
+    /// that was run with `--log-format internal-json`
+    pub fn stdin() -> Self {
+        Self::Stdin(StdinLines(BorrowLines::new(tokio::io::stdin())))
+    }
+
+    pub async fn next_line(&mut self) -> anyhow::Result<Option<(OutputStream, &[u8])>> {
+        match self {
+            Self::Process(streams) => streams.next_line().await,
+            Self::Record(record) => record.next_line().await,
+            // Nix writes its logs to stderr, which is where they are expected
+            Self::Stdin(StdinLines(lines)) => Ok(lines
+                .next_line()
+                .await?
+                .map(|line| (OutputStream::StdErr, line))),
+        }
+    }
+}
+
+pub struct StdinLines(BorrowLines<tokio::io::Stdin>);